@@ -1,75 +1,734 @@
-use anyhow::{anyhow, Result};
+use anyhow::{anyhow, Context, Result};
+use std::collections::BTreeMap;
 use std::fs::File;
 use std::io::{self, BufRead};
+use std::str::FromStr;
+
+/// What separates the values on a line.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub enum Delimiter {
+    /// Any run of spaces or tabs, as in the puzzle input.
+    #[default]
+    Whitespace,
+    Comma,
+    Tab,
+}
+
+impl Delimiter {
+    fn split<'a>(&self, line: &'a str) -> Vec<&'a str> {
+        match self {
+            Delimiter::Whitespace => line.split_whitespace().collect(),
+            Delimiter::Comma => line.split(',').map(str::trim).collect(),
+            Delimiter::Tab => line.split('\t').map(str::trim).collect(),
+        }
+    }
+}
+
+impl FromStr for Delimiter {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s {
+            "whitespace" => Ok(Delimiter::Whitespace),
+            "comma" | "," => Ok(Delimiter::Comma),
+            "tab" => Ok(Delimiter::Tab),
+            _ => Err(anyhow!("Unknown delimiter: {}", s)),
+        }
+    }
+}
+
+/// Layout of a file of lists, one list per column.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct ListFormat {
+    pub delimiter: Delimiter,
+    /// The first line names the columns instead of holding values.
+    pub header: bool,
+}
 
 struct Columns {
-    col1: Vec<i32>,
-    col2: Vec<i32>,
+    names: Vec<String>,
+    values: Vec<Vec<i32>>,
 }
 
-fn read_columns_from_file(file_path: &str) -> Result<Columns> {
+fn read_columns_from_file(file_path: &str, format: ListFormat) -> Result<Columns> {
     let file = File::open(file_path)?;
-    let reader = io::BufReader::new(file);
+    read_columns(io::BufReader::new(file), format)
+}
+
+fn read_columns(reader: impl BufRead, format: ListFormat) -> Result<Columns> {
+    let mut lines = reader
+        .lines()
+        .enumerate()
+        .map(|(i, line)| (i + 1, line))
+        .filter(|(_, line)| line.as_ref().map_or(true, |line| !line.trim().is_empty()));
 
-    let mut col1 = Vec::new();
-    let mut col2 = Vec::new();
+    let mut names = Vec::new();
+    if format.header {
+        let (_, line) = lines.next().ok_or_else(|| anyhow!("Missing header row"))?;
+        names = format
+            .delimiter
+            .split(&line?)
+            .into_iter()
+            .map(String::from)
+            .collect();
+        if names.len() < 2 {
+            return Err(anyhow!("Need at least two columns to compare"));
+        }
+    }
 
-    for line in reader.lines() {
+    let mut values: Vec<Vec<i32>> = vec![Vec::new(); names.len()];
+    for (line_no, line) in lines {
         let line = line?;
-        let mut numbers = line.split_whitespace();
-        let num1: i32 = numbers
-            .next()
-            .ok_or_else(|| anyhow!("Invalid line format"))?
-            .parse()?;
-        let num2: i32 = numbers
-            .next()
-            .ok_or_else(|| anyhow!("Invalid line format"))?
-            .parse()?;
-        col1.push(num1);
-        col2.push(num2);
+        let fields = format.delimiter.split(&line);
+        if names.is_empty() {
+            names = column_names(fields.len())?;
+            values = vec![Vec::new(); names.len()];
+        }
+        for (value, column) in parse_row(line_no, &fields, &names)?
+            .into_iter()
+            .zip(&mut values)
+        {
+            column.push(value);
+        }
+    }
+
+    if names.is_empty() {
+        // Nothing but blank lines: two empty lists, as the streaming path sees it
+        names = column_names(2)?;
+        values = vec![Vec::new(); 2];
+    }
+    Ok(Columns { names, values })
+}
+
+// Default names for a list without a header, which needs two columns to compare
+fn column_names(count: usize) -> Result<Vec<String>> {
+    if count < 2 {
+        return Err(anyhow!("Need at least two columns to compare"));
     }
+    Ok((1..=count).map(|i| format!("col{}", i)).collect())
+}
 
-    Ok(Columns { col1, col2 })
+// Values of a data line, which must have one for every column
+fn parse_row(line_no: usize, fields: &[&str], names: &[String]) -> Result<Vec<i32>> {
+    if fields.len() != names.len() {
+        return Err(anyhow!(
+            "line {}: expected {} values, found {}",
+            line_no,
+            names.len(),
+            fields.len()
+        ));
+    }
+    fields
+        .iter()
+        .zip(names)
+        .map(|(field, name)| {
+            field
+                .parse()
+                .with_context(|| format!("line {}: invalid {} value {:?}", line_no, name, field))
+        })
+        .collect()
+}
+
+type Counts = BTreeMap<i32, u64>;
+
+fn count_values(values: &[i32]) -> Counts {
+    let mut counts = Counts::new();
+    for &value in values {
+        *counts.entry(value).or_default() += 1;
+    }
+    counts
 }
 
-fn calculate_metrics(columns: &Columns) -> (i32, i32) {
-    let mut col1 = columns.col1.clone();
-    let mut col2 = columns.col2.clone();
+// `value * count` for a value repeated `count` times, if it fits in an i64
+fn weighted(value: i64, count: u64) -> Result<i64> {
+    i64::try_from(count)
+        .ok()
+        .and_then(|count| value.checked_mul(count))
+        .ok_or_else(|| anyhow!("{} * {} overflows i64", value, count))
+}
+
+// Walks both columns in sorted order a run of equal pairs at a time, so
+// repeated values cost one step rather than one per row.
+fn distance(col1: &Counts, col2: &Counts) -> Result<i64> {
+    let mut left = col1.iter().map(|(&v, &n)| (v, n));
+    let mut right = col2.iter().map(|(&v, &n)| (v, n));
+    let (mut a, mut b) = (left.next(), right.next());
+    let mut distance: i64 = 0;
+    while let (Some((num1, count1)), Some((num2, count2))) = (a, b) {
+        let run = count1.min(count2);
+        distance = distance
+            .checked_add(weighted((i64::from(num1) - i64::from(num2)).abs(), run)?)
+            .ok_or_else(|| anyhow!("Distance overflows i64"))?;
+        a = if count1 > run {
+            Some((num1, count1 - run))
+        } else {
+            left.next()
+        };
+        b = if count2 > run {
+            Some((num2, count2 - run))
+        } else {
+            right.next()
+        };
+    }
+    Ok(distance)
+}
+
+// What a value appearing `count` and `other` times in the two columns adds to
+// the similarity score
+fn similarity_of(num: i32, count: u64, other: u64) -> Result<i64> {
+    let repeats = count
+        .checked_mul(other)
+        .ok_or_else(|| anyhow!("{} * {} overflows u64", count, other))?;
+    weighted(i64::from(num), repeats)
+}
+
+fn similarity(col1: &Counts, col2: &Counts) -> Result<i64> {
+    col1.iter().try_fold(0i64, |total, (&num, &count)| {
+        let score = similarity_of(num, count, col2.get(&num).copied().unwrap_or(0))?;
+        total
+            .checked_add(score)
+            .ok_or_else(|| anyhow!("Similarity overflows i64"))
+    })
+}
+
+/// How often each value occurs in the two columns. This is all both metrics
+/// need, so inputs of any length can be streamed into it without keeping the
+/// rows around.
+#[derive(Default)]
+struct Tally {
+    col1: BTreeMap<i32, u64>,
+    col2: BTreeMap<i32, u64>,
+}
+
+impl Tally {
+    // Reads lines the way `read_columns` does without a header: blank lines are
+    // skipped and every line needs as many values as the first one.
+    fn from_reader(mut reader: impl BufRead) -> Result<Self> {
+        let mut tally = Tally::default();
+        let mut names = Vec::new();
+        let mut line = String::new();
+        let mut line_no = 0;
+        while reader.read_line(&mut line)? > 0 {
+            line_no += 1;
+            let fields = Delimiter::Whitespace.split(&line);
+            if !fields.is_empty() {
+                if names.is_empty() {
+                    names = column_names(fields.len())?;
+                }
+                let row = parse_row(line_no, &fields, &names)?;
+                tally.add(row[0], row[1]);
+            }
+            line.clear();
+        }
+        Ok(tally)
+    }
+
+    fn add(&mut self, num1: i32, num2: i32) {
+        *self.col1.entry(num1).or_default() += 1;
+        *self.col2.entry(num2).or_default() += 1;
+    }
+
+    fn distance(&self) -> Result<i64> {
+        distance(&self.col1, &self.col2)
+    }
+
+    fn similarity(&self) -> Result<i64> {
+        similarity(&self.col1, &self.col2)
+    }
+}
+
+/// Distance and similarity between every pair of columns, indexed like
+/// `Columns::names`. Both are symmetric.
+#[derive(Debug, PartialEq)]
+struct Matrices {
+    distance: Vec<Vec<i64>>,
+    similarity: Vec<Vec<i64>>,
+}
+
+fn comparison_matrices(columns: &Columns) -> Result<Matrices> {
+    let counts: Vec<Counts> = columns.values.iter().map(|v| count_values(v)).collect();
+    let table = |metric: fn(&Counts, &Counts) -> Result<i64>| {
+        counts
+            .iter()
+            .map(|a| counts.iter().map(|b| metric(a, b)).collect())
+            .collect::<Result<_>>()
+    };
+    Ok(Matrices {
+        distance: table(distance)?,
+        similarity: table(similarity)?,
+    })
+}
+
+// Puzzle metrics for the first two columns
+fn calculate_metrics(columns: &Columns) -> Result<(i64, i64)> {
+    let col1 = count_values(&columns.values[0]);
+    let col2 = count_values(&columns.values[1]);
+    Ok((distance(&col1, &col2)?, similarity(&col1, &col2)?))
+}
+
+/// Summary of a single column.
+#[derive(Debug, PartialEq)]
+struct ColumnStats {
+    min: i32,
+    max: i32,
+    median: f64,
+    /// Values with their counts, most frequent first.
+    most_frequent: Vec<(i32, u64)>,
+}
+
+/// A histogram bar covering `start..end`, with how many values of each column
+/// fall into it.
+#[derive(Debug, PartialEq)]
+struct Bucket {
+    start: i64,
+    end: i64,
+    counts: [u64; 2],
+}
+
+/// What we look at when sanity-checking the first two columns of a list.
+#[derive(Debug, PartialEq)]
+struct StatsReport {
+    columns: [Option<ColumnStats>; 2],
+    histogram: Vec<Bucket>,
+    /// Distinct values that appear in both columns.
+    shared: usize,
+    /// Sorted pairs `(left, right)` adding the most to the distance.
+    distance_contributors: Vec<(i32, i32, i64)>,
+    /// Values adding the most to the similarity score.
+    similarity_contributors: Vec<(i32, i64)>,
+}
+
+fn column_stats(values: &[i32], counts: &Counts, top: usize) -> Option<ColumnStats> {
+    let mut sorted = values.to_vec();
+    sorted.sort_unstable();
+    let (&min, &max) = (sorted.first()?, sorted.last()?);
+    let mid = sorted.len() / 2;
+    let median = if sorted.len().is_multiple_of(2) {
+        (f64::from(sorted[mid - 1]) + f64::from(sorted[mid])) / 2.0
+    } else {
+        f64::from(sorted[mid])
+    };
+
+    let mut most_frequent: Vec<(i32, u64)> = counts.iter().map(|(&v, &n)| (v, n)).collect();
+    most_frequent.sort_by(|a, b| b.1.cmp(&a.1).then(a.0.cmp(&b.0)));
+    most_frequent.truncate(top);
+
+    Some(ColumnStats {
+        min,
+        max,
+        median,
+        most_frequent,
+    })
+}
+
+// Splits the range both columns span into at most `buckets` equal bars
+fn histogram(columns: [&[i32]; 2], buckets: usize) -> Vec<Bucket> {
+    let all = || {
+        columns
+            .iter()
+            .flat_map(|column| column.iter())
+            .map(|&v| i64::from(v))
+    };
+    let (Some(min), Some(max)) = (all().min(), all().max()) else {
+        return Vec::new();
+    };
+    let width = ((max - min + 1) as u64).div_ceil(buckets.max(1) as u64) as i64;
+
+    let mut histogram: Vec<Bucket> = (min..=max)
+        .step_by(width as usize)
+        .map(|start| Bucket {
+            start,
+            end: start + width,
+            counts: [0; 2],
+        })
+        .collect();
+    for (i, column) in columns.iter().enumerate() {
+        for &value in column.iter() {
+            histogram[((i64::from(value) - min) / width) as usize].counts[i] += 1;
+        }
+    }
+    histogram
+}
 
-    col1.sort_unstable();
-    col2.sort_unstable();
+fn stats_report(columns: &Columns, top: usize, buckets: usize) -> Result<StatsReport> {
+    let (left, right) = (&columns.values[0], &columns.values[1]);
+    let (col1, col2) = (count_values(left), count_values(right));
 
-    let distance: i32 = col1
+    let mut pairs: Vec<(i32, i32)> = {
+        let (mut left, mut right) = (left.clone(), right.clone());
+        left.sort_unstable();
+        right.sort_unstable();
+        left.into_iter().zip(right).collect()
+    };
+    pairs.sort_by_key(|&(a, b)| std::cmp::Reverse((i64::from(a) - i64::from(b)).abs()));
+    let distance_contributors = pairs
+        .into_iter()
+        .take(top)
+        .map(|(a, b)| (a, b, (i64::from(a) - i64::from(b)).abs()))
+        .collect();
+
+    let mut similarity_contributors: Vec<(i32, i64)> = col1
         .iter()
-        .zip(col2.iter())
-        .map(|(num1, num2)| (num1 - num2).abs())
-        .sum();
+        .filter_map(|(&value, &count)| {
+            let other = *col2.get(&value)?;
+            Some(similarity_of(value, count, other).map(|score| (value, score)))
+        })
+        .collect::<Result<_>>()?;
+    let shared = similarity_contributors.len();
+    similarity_contributors.sort_by(|a, b| b.1.cmp(&a.1).then(a.0.cmp(&b.0)));
+    similarity_contributors.truncate(top);
+
+    Ok(StatsReport {
+        columns: [
+            column_stats(left, &col1, top),
+            column_stats(right, &col2, top),
+        ],
+        histogram: histogram([left, right], buckets),
+        shared,
+        distance_contributors,
+        similarity_contributors,
+    })
+}
 
-    let similarity_score: i32 = col1
+fn print_stats(names: &[String], report: &StatsReport) {
+    for (name, stats) in names.iter().zip(&report.columns) {
+        match stats {
+            Some(stats) => println!(
+                "{}: min {}, max {}, median {}, most frequent {:?}",
+                name, stats.min, stats.max, stats.median, stats.most_frequent
+            ),
+            None => println!("{}: empty", name),
+        }
+    }
+    println!("Shared values: {}", report.shared);
+    println!("Histogram ({} / {}):", names[0], names[1]);
+    for bucket in &report.histogram {
+        println!(
+            "{:>8}..{:<8} {:>6} {:>6}",
+            bucket.start, bucket.end, bucket.counts[0], bucket.counts[1]
+        );
+    }
+    println!("Top distance pairs: {:?}", report.distance_contributors);
+    println!(
+        "Top similarity values: {:?}",
+        report.similarity_contributors
+    );
+}
+
+fn print_matrix(title: &str, names: &[String], rows: &[Vec<i64>]) {
+    let width = rows
         .iter()
-        .map(|&num1| num1 * col2.iter().filter(|&&num2| num2 == num1).count() as i32)
-        .sum();
+        .flatten()
+        .map(|value| value.to_string().len())
+        .chain(names.iter().map(String::len))
+        .max()
+        .unwrap_or(0);
+    println!("{}", title);
+    print!("{:width$}", "", width = width);
+    for name in names {
+        print!("  {:>width$}", name, width = width);
+    }
+    println!();
+    for (name, row) in names.iter().zip(rows) {
+        print!("{:width$}", name, width = width);
+        for value in row {
+            print!("  {:>width$}", value, width = width);
+        }
+        println!();
+    }
+}
 
-    (distance, similarity_score)
+pub fn solve(file_path: &str) -> Result<(i64, i64)> {
+    let tally = Tally::from_reader(io::BufReader::new(File::open(file_path)?))?;
+    Ok((tally.distance()?, tally.similarity()?))
 }
 
-pub fn solve(file_path: &str) -> Result<(i32, i32)> {
-    let columns = read_columns_from_file(file_path)?;
-    Ok(calculate_metrics(&columns))
+/// Prints the solve result, streaming the input through `Tally`. `--in-memory`
+/// loads every row first instead, to cross-check the streaming path.
+/// `--input PATH` reads another list than the puzzle input, and
+/// `--delimiter whitespace|comma|tab` and `--header` describe its layout.
+/// `--matrix` compares all of its columns pairwise. `--stats` reports on the
+/// first two columns instead.
+pub fn run(input: &str, args: &[String]) -> Result<()> {
+    let mut input = input;
+    let mut in_memory = false;
+    let mut matrix = false;
+    let mut stats = false;
+    let mut format = ListFormat::default();
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--in-memory" => in_memory = true,
+            "--matrix" => matrix = true,
+            "--stats" => stats = true,
+            "--header" => format.header = true,
+            "--input" => input = args.next().ok_or_else(|| anyhow!("--input needs a path"))?,
+            "--delimiter" => {
+                format.delimiter = args
+                    .next()
+                    .ok_or_else(|| anyhow!("--delimiter needs a value"))?
+                    .parse()?
+            }
+            _ => return Err(anyhow!("Unknown option: {}", arg)),
+        }
+    }
+
+    if matrix {
+        let columns = read_columns_from_file(input, format)?;
+        let matrices = comparison_matrices(&columns)?;
+        print_matrix("Distance", &columns.names, &matrices.distance);
+        print_matrix("Similarity", &columns.names, &matrices.similarity);
+        return Ok(());
+    }
+
+    if stats {
+        let columns = read_columns_from_file(input, format)?;
+        print_stats(&columns.names, &stats_report(&columns, 5, 10)?);
+        return Ok(());
+    }
+
+    let result = if in_memory || format.header || format.delimiter != Delimiter::Whitespace {
+        calculate_metrics(&read_columns_from_file(input, format)?)?
+    } else {
+        solve(input)?
+    };
+    println!("Day 01 result: {:?}", result);
+    Ok(())
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
 
+    fn columns(values: &[&[i32]]) -> Columns {
+        Columns {
+            names: (1..=values.len()).map(|i| format!("col{}", i)).collect(),
+            values: values.iter().map(|v| v.to_vec()).collect(),
+        }
+    }
+
     #[test]
     fn test_calculate_metrics() {
-        let columns = Columns {
-            col1: vec![3, 4, 2, 1, 3, 3],
-            col2: vec![4, 3, 5, 3, 9, 3],
-        };
-        let (distance, similarity_score) = calculate_metrics(&columns);
+        let columns = columns(&[&[3, 4, 2, 1, 3, 3], &[4, 3, 5, 3, 9, 3]]);
+        let (distance, similarity_score) = calculate_metrics(&columns).unwrap();
         assert_eq!(distance, 11);
         assert_eq!(similarity_score, 31);
     }
+
+    #[test]
+    fn test_streamed_tally() {
+        let input = "3   4\n4   3\n2   5\n1   3\n3   9\n3   3\n";
+        let tally = Tally::from_reader(input.as_bytes()).unwrap();
+        assert_eq!(
+            (tally.distance().unwrap(), tally.similarity().unwrap()),
+            (11, 31)
+        );
+
+        let err = Tally::from_reader("1 2\n3\n".as_bytes()).err().unwrap();
+        assert!(format!("{:?}", err).contains("line 2"));
+    }
+
+    #[test]
+    fn test_metrics_do_not_overflow() {
+        let columns = columns(&[&[i32::MIN, i32::MAX, i32::MAX], &[i32::MAX; 3]]);
+        let (distance, similarity_score) = calculate_metrics(&columns).unwrap();
+        assert_eq!(distance, u32::MAX as i64);
+        assert_eq!(similarity_score, 6 * i32::MAX as i64);
+    }
+
+    #[test]
+    fn test_runs_of_repeated_values() {
+        // Sorted: [1, 1, 1, 5] against [2, 2, 4, 4]
+        let columns = columns(&[&[1, 5, 1, 1], &[4, 2, 4, 2]]);
+        assert_eq!(calculate_metrics(&columns).unwrap(), (1 + 1 + 3 + 1, 0));
+    }
+
+    #[test]
+    fn test_read_columns_with_header() {
+        let input = "left,right,extra\n3, 4,1\n4,3,1\n\n2,5,1\n";
+        let format = ListFormat {
+            delimiter: Delimiter::Comma,
+            header: true,
+        };
+        let columns = read_columns(input.as_bytes(), format).unwrap();
+        assert_eq!(columns.names, ["left", "right", "extra"]);
+        assert_eq!(
+            columns.values,
+            [vec![3, 4, 2], vec![4, 3, 5], vec![1, 1, 1]]
+        );
+
+        let columns = read_columns(
+            "1\t2\n3\t4\n".as_bytes(),
+            ListFormat {
+                delimiter: Delimiter::Tab,
+                header: false,
+            },
+        )
+        .unwrap();
+        assert_eq!(columns.names, ["col1", "col2"]);
+    }
+
+    #[test]
+    fn test_read_columns_errors() {
+        let format = ListFormat::default();
+        let err = read_columns("1 2\n3 4 5\n".as_bytes(), format)
+            .err()
+            .unwrap();
+        assert_eq!(err.to_string(), "line 2: expected 2 values, found 3");
+
+        let format = ListFormat {
+            delimiter: Delimiter::Comma,
+            header: true,
+        };
+        let err = read_columns("a,b\n1,x\n".as_bytes(), format).err().unwrap();
+        assert_eq!(err.to_string(), "line 2: invalid b value \"x\"");
+
+        assert!(read_columns("1\n2\n".as_bytes(), ListFormat::default()).is_err());
+    }
+
+    #[test]
+    fn test_header_needs_two_columns() {
+        let format = ListFormat {
+            delimiter: Delimiter::Whitespace,
+            header: true,
+        };
+        let err = read_columns("a\n1\n2\n".as_bytes(), format).err().unwrap();
+        assert_eq!(err.to_string(), "Need at least two columns to compare");
+        assert!(read_columns("a b\n1 2\n".as_bytes(), format).is_ok());
+    }
+
+    #[test]
+    fn test_comparison_matrices() {
+        let columns = columns(&[
+            &[3, 4, 2, 1, 3, 3],
+            &[4, 3, 5, 3, 9, 3],
+            &[3, 3, 3, 3, 3, 3],
+        ]);
+        let matrices = comparison_matrices(&columns).unwrap();
+        assert_eq!(
+            matrices.distance,
+            [vec![0, 11, 4], vec![11, 0, 9], vec![4, 9, 0]]
+        );
+        assert_eq!(
+            matrices.similarity,
+            [vec![34, 31, 54], vec![31, 45, 54], vec![54, 54, 108]]
+        );
+    }
+
+    #[test]
+    fn test_stats_report() {
+        let columns = columns(&[&[3, 4, 2, 1, 3, 3], &[4, 3, 5, 3, 9, 3]]);
+        let report = stats_report(&columns, 2, 3).unwrap();
+        assert_eq!(
+            report.columns,
+            [
+                Some(ColumnStats {
+                    min: 1,
+                    max: 4,
+                    median: 3.0,
+                    most_frequent: vec![(3, 3), (1, 1)],
+                }),
+                Some(ColumnStats {
+                    min: 3,
+                    max: 9,
+                    median: 3.5,
+                    most_frequent: vec![(3, 3), (4, 1)],
+                }),
+            ]
+        );
+        assert_eq!(report.shared, 2);
+        // Values 1..=9 in three bars of three
+        assert_eq!(
+            report.histogram,
+            [
+                Bucket {
+                    start: 1,
+                    end: 4,
+                    counts: [5, 3],
+                },
+                Bucket {
+                    start: 4,
+                    end: 7,
+                    counts: [1, 2],
+                },
+                Bucket {
+                    start: 7,
+                    end: 10,
+                    counts: [0, 1],
+                },
+            ]
+        );
+        assert_eq!(report.distance_contributors, [(4, 9, 5), (1, 3, 2)]);
+        assert_eq!(report.similarity_contributors, [(3, 27), (4, 4)]);
+    }
+
+    #[test]
+    fn test_stats_of_empty_columns() {
+        let report = stats_report(&columns(&[&[], &[]]), 5, 10).unwrap();
+        assert_eq!(report.columns, [None, None]);
+        assert!(report.histogram.is_empty());
+        assert_eq!(report.shared, 0);
+    }
+
+    #[test]
+    fn test_metric_overflow_is_an_error() {
+        // 2^34 matches of i32::MAX, counted without expanding the rows
+        let col1 = Counts::from([(i32::MAX, 1 << 17)]);
+        let col2 = Counts::from([(i32::MAX, 1 << 17)]);
+        assert!(similarity(&col1, &col2).is_err());
+        let far = |value| Counts::from([(value, 1 << 40)]);
+        assert!(distance(&far(i32::MAX), &far(i32::MIN)).is_err());
+
+        let col2 = Counts::from([(i32::MAX, u64::MAX)]);
+        assert!(similarity(&col1, &col2).is_err());
+    }
+
+    #[test]
+    fn test_streamed_and_in_memory_paths_agree() {
+        let both = |input: &str| {
+            let streamed = Tally::from_reader(input.as_bytes())
+                .and_then(|tally| Ok((tally.distance()?, tally.similarity()?)));
+            let in_memory = read_columns(input.as_bytes(), ListFormat::default())
+                .and_then(|columns| calculate_metrics(&columns));
+            (streamed.ok(), in_memory.ok())
+        };
+        for input in [
+            "3   4\n4   3\n2   5\n1   3\n3   9\n3   3\n",
+            "\n3 4\n  \n4 3\r\n\n2 5\n1 3\n3 9\n3 3\n\n",
+            "3 4 7\n4 3 7\n",
+            "",
+            "\n \n",
+        ] {
+            let (streamed, in_memory) = both(input);
+            assert!(streamed.is_some(), "{:?}", input);
+            assert_eq!(streamed, in_memory, "{:?}", input);
+        }
+        assert_eq!(both("\n \n"), (Some((0, 0)), Some((0, 0))));
+        for input in ["1 2\n3\n", "1 2\n3 4 5\n", "1\n2\n", "1 x\n"] {
+            assert_eq!(both(input), (None, None), "{:?}", input);
+        }
+    }
+
+    #[test]
+    fn test_run_on_other_input() {
+        let path = std::env::temp_dir().join(format!("day01-{}.csv", std::process::id()));
+        std::fs::write(&path, "left,right\n3,4\n4,3\n2,5\n1,3\n3,9\n3,3\n").unwrap();
+        let args = |args: &[&str]| args.iter().map(|a| a.to_string()).collect::<Vec<_>>();
+        let path_arg = path.to_str().unwrap();
+
+        let result = run(
+            "inputs/day01.txt",
+            &args(&["--input", path_arg, "--delimiter", "comma", "--header"]),
+        );
+        // Without --header the names don't parse as values
+        let headless = run(
+            "inputs/day01.txt",
+            &args(&["--input", path_arg, "--delimiter", "comma"]),
+        );
+        std::fs::remove_file(&path).unwrap();
+
+        result.unwrap();
+        assert!(headless.is_err());
+        assert!(run("inputs/day01.txt", &args(&["--input"])).is_err());
+    }
 }
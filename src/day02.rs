@@ -1,4 +1,6 @@
-use anyhow::Result;
+use anyhow::{anyhow, Result};
+use itertools::Itertools;
+use std::fmt;
 use std::fs::File;
 use std::io::{self, BufRead};
 
@@ -23,48 +25,170 @@ fn read_reports_from_file(file_path: &str) -> Result<Reports> {
     Ok(reports)
 }
 
-fn is_safe_report(levels: &[u32]) -> bool {
-    if levels.len() < 2 {
-        return true;
-    }
+/// What makes a report safe. The default is the puzzle's: levels strictly
+/// increasing or strictly decreasing by 1 to 3 at a time.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct SafetyPolicy {
+    pub min_step: u32,
+    pub max_step: u32,
+    /// Adjacent levels may be equal.
+    pub allow_plateaus: bool,
+    /// All steps must go the same way.
+    pub require_direction: bool,
+}
 
-    let mut is_increasing = true;
-    let mut is_decreasing = true;
+impl Default for SafetyPolicy {
+    fn default() -> Self {
+        SafetyPolicy {
+            min_step: 1,
+            max_step: 3,
+            allow_plateaus: false,
+            require_direction: true,
+        }
+    }
+}
 
-    for i in 1..levels.len() {
-        let diff = (levels[i] as i32 - levels[i - 1] as i32).abs();
-        if diff < 1 || diff > 3 {
+impl SafetyPolicy {
+    // Whether `to` may follow `from` in a report going up (or down, when the
+    // policy requires a direction)
+    fn allows(&self, from: u32, to: u32, increasing: bool) -> bool {
+        let diff = i64::from(to) - i64::from(from);
+        if diff == 0 {
+            return self.allow_plateaus;
+        }
+        if self.require_direction && (diff > 0) != increasing {
             return false;
         }
-        if levels[i] > levels[i - 1] {
-            is_decreasing = false;
-        } else if levels[i] < levels[i - 1] {
-            is_increasing = false;
+        (i64::from(self.min_step)..=i64::from(self.max_step)).contains(&diff.abs())
+    }
+
+    fn directions(&self) -> &'static [bool] {
+        if self.require_direction {
+            &[true, false]
+        } else {
+            &[true]
         }
     }
+}
+
+fn is_safe_report(levels: &[u32], policy: &SafetyPolicy) -> bool {
+    is_safe_sequence(levels.iter().copied(), policy)
+}
+
+fn is_safe_sequence(levels: impl Iterator<Item = u32> + Clone, policy: &SafetyPolicy) -> bool {
+    policy.directions().iter().any(|&increasing| {
+        levels
+            .clone()
+            .tuple_windows()
+            .all(|(from, to)| policy.allows(from, to, increasing))
+    })
+}
 
-    is_increasing || is_decreasing
+/// Why a level breaks a report.
+#[derive(Debug, PartialEq)]
+pub enum Violation {
+    StepTooLarge(u32),
+    StepTooSmall(u32),
+    ZeroStep,
+    /// The report turns against the direction its first step set.
+    DirectionChange,
 }
 
-fn can_be_safe_by_removing_one(levels: &[u32]) -> bool {
-    for i in 0..levels.len() {
-        let mut temp_levels = levels.to_vec();
-        temp_levels.remove(i);
-        if is_safe_report(&temp_levels) {
-            return true;
+impl fmt::Display for Violation {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Violation::StepTooLarge(step) => write!(f, "step of {} is too large", step),
+            Violation::StepTooSmall(step) => write!(f, "step of {} is too small", step),
+            Violation::ZeroStep => write!(f, "level repeats the previous one"),
+            Violation::DirectionChange => write!(f, "direction changes"),
         }
     }
-    false
 }
 
-fn count_safe_reports(reports: &Reports) -> (i32, i32) {
+/// What is wrong with an unsafe report.
+#[derive(Debug, PartialEq)]
+pub struct Diagnosis {
+    /// First level that can't follow the one before it.
+    pub index: usize,
+    pub violation: Violation,
+    /// Index of a level whose removal makes the report safe, if one does.
+    pub fix: Option<usize>,
+}
+
+// None for a safe report
+fn diagnose(levels: &[u32], policy: &SafetyPolicy) -> Option<Diagnosis> {
+    let mut increasing = None;
+    for (i, pair) in levels.windows(2).enumerate() {
+        let diff = i64::from(pair[1]) - i64::from(pair[0]);
+        let step = diff.unsigned_abs() as u32;
+        let violation = if diff == 0 {
+            (!policy.allow_plateaus).then_some(Violation::ZeroStep)
+        } else if policy.require_direction && increasing.is_some_and(|up| up != (diff > 0)) {
+            Some(Violation::DirectionChange)
+        } else if step > policy.max_step {
+            Some(Violation::StepTooLarge(step))
+        } else if step < policy.min_step {
+            Some(Violation::StepTooSmall(step))
+        } else {
+            None
+        };
+
+        if let Some(violation) = violation {
+            let fix = (0..levels.len()).find(|&skip| {
+                let rest = levels
+                    .iter()
+                    .enumerate()
+                    .filter(move |&(j, _)| j != skip)
+                    .map(|(_, &level)| level);
+                is_safe_sequence(rest, policy)
+            });
+            return Some(Diagnosis {
+                index: i + 1,
+                violation,
+                fix,
+            });
+        }
+        if diff != 0 {
+            increasing.get_or_insert(diff > 0);
+        }
+    }
+    None
+}
+
+// Whether the report becomes safe after dropping at most `max_removals` levels.
+// For each direction, `removed[i]` is the fewest levels dropped from the first
+// `i + 1` so that the kept ones form a safe run ending at level `i`. Only the
+// `max_removals` levels right before `i` can precede it, so this is O(n·k).
+fn can_be_safe_by_removing(levels: &[u32], max_removals: usize, policy: &SafetyPolicy) -> bool {
+    let n = levels.len();
+    if n <= max_removals + 1 {
+        return true;
+    }
+
+    policy.directions().iter().any(|&increasing| {
+        let mut removed = vec![usize::MAX; n];
+        for i in 0..n {
+            removed[i] = i;
+            for j in i.saturating_sub(max_removals + 1)..i {
+                if removed[j] != usize::MAX && policy.allows(levels[j], levels[i], increasing) {
+                    removed[i] = removed[i].min(removed[j] + (i - j - 1));
+                }
+            }
+        }
+        (0..n).any(|i| removed[i] + (n - 1 - i) <= max_removals)
+    })
+}
+
+// Counts the reports that are safe as they are, and those that need between one
+// and `max_removals` levels removed to become safe.
+fn count_safe_reports(reports: &Reports, max_removals: usize, policy: &SafetyPolicy) -> (i32, i32) {
     let mut safe_count = 0;
     let mut unsafe_tolerance_count = 0;
 
     for levels in reports {
-        if is_safe_report(levels) {
+        if is_safe_report(levels, policy) {
             safe_count += 1;
-        } else if can_be_safe_by_removing_one(levels) {
+        } else if can_be_safe_by_removing(levels, max_removals, policy) {
             unsafe_tolerance_count += 1;
         }
     }
@@ -74,44 +198,259 @@ fn count_safe_reports(reports: &Reports) -> (i32, i32) {
 
 pub fn solve(file_path: &str) -> Result<(i32, i32)> {
     let reports = read_reports_from_file(file_path)?;
-    let (safe, toleranced) = count_safe_reports(&reports);
+    let (safe, toleranced) = count_safe_reports(&reports, 1, &SafetyPolicy::default());
     Ok((safe, safe + toleranced))
 }
 
+/// Prints how many reports are safe, and how many more become safe with up to
+/// `--tolerance K` levels removed (1 by default). `--min-step N`,
+/// `--max-step N`, `--plateaus` and `--any-direction` loosen or tighten the
+/// safety rules. `--explain` lists every unsafe report with the level that
+/// breaks it and which single removal, if any, fixes it.
+pub fn run(input: &str, args: &[String]) -> Result<()> {
+    let mut policy = SafetyPolicy::default();
+    let mut tolerance = 1;
+    let mut explain = false;
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        let mut value = || args.next().ok_or_else(|| anyhow!("{} needs a value", arg));
+        match arg.as_str() {
+            "--tolerance" => tolerance = value()?.parse()?,
+            "--min-step" => policy.min_step = value()?.parse()?,
+            "--max-step" => policy.max_step = value()?.parse()?,
+            "--plateaus" => policy.allow_plateaus = true,
+            "--any-direction" => policy.require_direction = false,
+            "--explain" => explain = true,
+            _ => return Err(anyhow!("Unknown option: {}", arg)),
+        }
+    }
+
+    let reports = read_reports_from_file(input)?;
+    if explain {
+        for (line, levels) in reports.iter().enumerate() {
+            let Some(diagnosis) = diagnose(levels, &policy) else {
+                continue;
+            };
+            let fix = match diagnosis.fix {
+                Some(index) => format!("removing level {} fixes it", index),
+                None => "no single removal fixes it".to_string(),
+            };
+            println!(
+                "line {} {:?}: level {} ({}): {}; {}",
+                line + 1,
+                levels,
+                diagnosis.index,
+                levels[diagnosis.index],
+                diagnosis.violation,
+                fix
+            );
+        }
+    }
+
+    let (safe, toleranced) = count_safe_reports(&reports, tolerance, &policy);
+    println!(
+        "Day 02 result ({:?}, tolerance {}): safe {}, safe with removals {}",
+        policy,
+        tolerance,
+        safe,
+        safe + toleranced
+    );
+    Ok(())
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
 
     #[test]
     fn test_is_safe_report() {
-        assert!(is_safe_report(&[7, 6, 4, 2, 1])); // safe (decreasing)
-        assert!(!is_safe_report(&[1, 2, 7, 8, 9])); // not safe (difference > 3)
-        assert!(!is_safe_report(&[9, 7, 6, 2, 1])); // not safe (difference > 3)
-        assert!(!is_safe_report(&[1, 3, 2, 4, 5])); // not safe (not all increasing or decreasing)
-        assert!(!is_safe_report(&[8, 6, 4, 4, 1])); // not safe (difference < 1)
-        assert!(is_safe_report(&[1, 3, 6, 7, 9])); // safe (increasing)
-        assert!(is_safe_report(&[1])); // safe (single element)
-        assert!(is_safe_report(&[])); // safe (empty)
+        let policy = SafetyPolicy::default();
+        assert!(is_safe_report(&[7, 6, 4, 2, 1], &policy)); // safe (decreasing)
+        assert!(!is_safe_report(&[1, 2, 7, 8, 9], &policy)); // not safe (difference > 3)
+        assert!(!is_safe_report(&[9, 7, 6, 2, 1], &policy)); // not safe (difference > 3)
+        assert!(!is_safe_report(&[1, 3, 2, 4, 5], &policy)); // not safe (not all increasing or decreasing)
+        assert!(!is_safe_report(&[8, 6, 4, 4, 1], &policy)); // not safe (difference < 1)
+        assert!(is_safe_report(&[1, 3, 6, 7, 9], &policy)); // safe (increasing)
+        assert!(is_safe_report(&[1], &policy)); // safe (single element)
+        assert!(is_safe_report(&[], &policy)); // safe (empty)
     }
 
     #[test]
     fn test_can_be_safe_by_removing_one() {
-        assert!(!can_be_safe_by_removing_one(&[1, 2, 7, 8, 9])); // not safe
-        assert!(!can_be_safe_by_removing_one(&[9, 7, 6, 2, 1])); // not safe
-        assert!(can_be_safe_by_removing_one(&[1, 3, 2, 4, 5])); // becomes safe by removing 2
-        assert!(can_be_safe_by_removing_one(&[8, 6, 4, 4, 1])); // can be safe by removing 4
+        let policy = SafetyPolicy::default();
+        assert!(!can_be_safe_by_removing(&[1, 2, 7, 8, 9], 1, &policy)); // not safe
+        assert!(!can_be_safe_by_removing(&[9, 7, 6, 2, 1], 1, &policy)); // not safe
+        assert!(can_be_safe_by_removing(&[1, 3, 2, 4, 5], 1, &policy)); // becomes safe by removing 2
+        assert!(can_be_safe_by_removing(&[8, 6, 4, 4, 1], 1, &policy)); // can be safe by removing 4
     }
 
-    #[test]
-    fn test_count_safe_reports() {
-        let reports = vec![
+    fn example() -> Reports {
+        vec![
             vec![7, 6, 4, 2, 1], // safe (decreasing)
             vec![1, 2, 7, 8, 9], // not safe (difference > 3)
             vec![9, 7, 6, 2, 1], // not safe (difference > 3)
             vec![1, 3, 2, 4, 5], // not safe (not all increasing or decreasing)
             vec![8, 6, 4, 4, 1], // not safe (difference < 1)
             vec![1, 3, 6, 7, 9], // safe (increasing)
+        ]
+    }
+
+    #[test]
+    fn test_count_safe_reports() {
+        let reports = example();
+        let policy = SafetyPolicy::default();
+        assert_eq!(count_safe_reports(&reports, 1, &policy), (2, 2));
+        assert_eq!(count_safe_reports(&reports, 0, &policy), (2, 0));
+        assert_eq!(count_safe_reports(&reports, 2, &policy), (2, 4));
+    }
+
+    #[test]
+    fn test_safety_policies() {
+        let plateaus = SafetyPolicy {
+            allow_plateaus: true,
+            ..SafetyPolicy::default()
+        };
+        assert!(is_safe_report(&[8, 6, 4, 4, 1], &plateaus));
+        assert!(!is_safe_report(&[1, 3, 2, 4, 5], &plateaus));
+
+        let any_direction = SafetyPolicy {
+            require_direction: false,
+            ..SafetyPolicy::default()
+        };
+        assert!(is_safe_report(&[1, 3, 2, 4, 5], &any_direction));
+        assert!(!is_safe_report(&[8, 6, 4, 4, 1], &any_direction));
+
+        let wide = SafetyPolicy {
+            min_step: 2,
+            max_step: 5,
+            ..SafetyPolicy::default()
+        };
+        assert!(is_safe_report(&[1, 3, 8, 10], &wide));
+        assert!(!is_safe_report(&[7, 6, 4, 2, 1], &wide));
+
+        assert_eq!(count_safe_reports(&example(), 0, &plateaus), (3, 0));
+        assert_eq!(count_safe_reports(&example(), 0, &any_direction), (3, 0));
+        assert_eq!(count_safe_reports(&example(), 1, &wide), (0, 2));
+    }
+
+    // The straightforward check: try every way of dropping up to `k` levels
+    fn brute_force(levels: &[u32], k: usize, policy: &SafetyPolicy) -> bool {
+        is_safe_report(levels, policy)
+            || (k > 0
+                && (0..levels.len()).any(|i| {
+                    let mut rest = levels.to_vec();
+                    rest.remove(i);
+                    brute_force(&rest, k - 1, policy)
+                }))
+    }
+
+    #[test]
+    fn test_can_be_safe_by_removing_k() {
+        let policy = SafetyPolicy::default();
+        assert!(!can_be_safe_by_removing(&[1, 3, 2, 4, 5], 0, &policy));
+        assert!(!can_be_safe_by_removing(&[1, 2, 7, 8, 9], 1, &policy));
+        assert!(can_be_safe_by_removing(&[1, 2, 7, 8, 9], 2, &policy)); // drop 7 and 8
+        assert!(can_be_safe_by_removing(&[9, 7, 6, 2, 1], 2, &policy)); // drop 2 and 1
+        assert!(can_be_safe_by_removing(&[1, 9, 9, 9, 2, 3], 3, &policy));
+        assert!(!can_be_safe_by_removing(&[1, 9, 9, 9, 2, 3], 2, &policy));
+        assert!(can_be_safe_by_removing(&[], 0, &policy));
+    }
+
+    #[test]
+    fn test_removals_match_brute_force() {
+        let policies = [
+            SafetyPolicy::default(),
+            SafetyPolicy {
+                allow_plateaus: true,
+                require_direction: false,
+                ..SafetyPolicy::default()
+            },
+            SafetyPolicy {
+                min_step: 2,
+                max_step: 4,
+                ..SafetyPolicy::default()
+            },
         ];
-        assert_eq!(count_safe_reports(&reports), (2, 2));
+        // Every report of five levels drawn from 0..6
+        for code in 0..6u32.pow(5) {
+            let levels: Vec<u32> = (0..5).map(|i| code / 6u32.pow(i) % 6).collect();
+            for (policy, k) in policies.iter().flat_map(|p| (0..3).map(move |k| (p, k))) {
+                assert_eq!(
+                    can_be_safe_by_removing(&levels, k, policy),
+                    brute_force(&levels, k, policy),
+                    "{:?} with k = {} under {:?}",
+                    levels,
+                    k,
+                    policy
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn test_diagnose() {
+        let policy = SafetyPolicy::default();
+        let diagnoses: Vec<_> = example()
+            .iter()
+            .map(|levels| diagnose(levels, &policy))
+            .collect();
+        assert_eq!(
+            diagnoses,
+            [
+                None,
+                Some(Diagnosis {
+                    index: 2,
+                    violation: Violation::StepTooLarge(5),
+                    fix: None,
+                }),
+                Some(Diagnosis {
+                    index: 3,
+                    violation: Violation::StepTooLarge(4),
+                    fix: None,
+                }),
+                Some(Diagnosis {
+                    index: 2,
+                    violation: Violation::DirectionChange,
+                    fix: Some(1),
+                }),
+                Some(Diagnosis {
+                    index: 3,
+                    violation: Violation::ZeroStep,
+                    fix: Some(2),
+                }),
+                None,
+            ]
+        );
+
+        let wide = SafetyPolicy {
+            min_step: 2,
+            ..SafetyPolicy::default()
+        };
+        assert_eq!(
+            diagnose(&[1, 3, 4, 6], &wide),
+            Some(Diagnosis {
+                index: 2,
+                violation: Violation::StepTooSmall(1),
+                fix: Some(1),
+            })
+        );
+    }
+
+    #[test]
+    fn test_diagnosis_agrees_with_safety() {
+        let policy = SafetyPolicy::default();
+        for code in 0..6u32.pow(5) {
+            let levels: Vec<u32> = (0..5).map(|i| code / 6u32.pow(i) % 6).collect();
+            let diagnosis = diagnose(&levels, &policy);
+            assert_eq!(diagnosis.is_none(), is_safe_report(&levels, &policy));
+            if let Some(diagnosis) = diagnosis {
+                assert_eq!(
+                    diagnosis.fix.is_some(),
+                    can_be_safe_by_removing(&levels, 1, &policy),
+                    "{:?}",
+                    levels
+                );
+            }
+        }
     }
 }
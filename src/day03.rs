@@ -1,54 +1,827 @@
-use anyhow::Result;
-use regex::Regex;
-use std::fs::File;
+use anyhow::{anyhow, Result};
+use std::fs::{self, File};
 use std::io::Read;
 
-fn read_file(file_path: &str) -> Result<String> {
-    let mut file = File::open(file_path)?;
-    let mut contents = String::new();
-    file.read_to_string(&mut contents)?;
-    Ok(contents)
+/// What an instruction does when it runs.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Effect {
+    /// Adds to the totals, or feeds the enclosing call when nested.
+    Value(i64),
+    Enable,
+    Disable,
 }
 
-fn parse_and_execute(program: &str) -> (i64, i64) {
-    let re = Regex::new(r#"(?<mul>mul\((\d{1,3}),(\d{1,3})\))|(?<dont>don't\(\))|(?<do>do\(\))"#)
-        .unwrap();
-    let mut uncorrupted_result = 0;
-    let mut enhanced_result = 0;
-    let mut dont = false;
-
-    for cap in re.captures_iter(program) {
-        if let Some(_) = cap.name("mul") {
-            let x: i64 = cap[2].parse().unwrap();
-            let y: i64 = cap[3].parse().unwrap();
-            uncorrupted_result += x * y;
-            if !dont {
-                enhanced_result += x * y;
+/// An instruction the interpreter understands, written `name(args)`.
+pub trait Opcode: Sync {
+    fn name(&self) -> &'static str;
+
+    /// Number of arguments taken by the default `parse_args`.
+    fn arity(&self) -> usize {
+        2
+    }
+
+    /// Reads the arguments between the parentheses, `arity` comma-separated
+    /// operands unless overridden.
+    fn parse_args(&self, parser: &mut Parser) -> Option<Vec<i64>> {
+        let mut args = Vec::with_capacity(self.arity());
+        for i in 0..self.arity() {
+            if i > 0 {
+                parser.literal(b",")?;
             }
-        } else if cap.name("dont").is_some() {
-            dont = true;
-        } else if cap.name("do").is_some() {
-            dont = false;
+            args.push(parser.operand()?);
+        }
+        Some(args)
+    }
+
+    /// `None` if the arguments make the instruction invalid, e.g. on overflow.
+    fn execute(&self, args: &[i64]) -> Option<Effect>;
+}
+
+pub struct Mul;
+pub struct Add;
+pub struct Sub;
+pub struct Do;
+pub struct Dont;
+
+impl Opcode for Mul {
+    fn name(&self) -> &'static str {
+        "mul"
+    }
+
+    fn execute(&self, args: &[i64]) -> Option<Effect> {
+        args[0].checked_mul(args[1]).map(Effect::Value)
+    }
+}
+
+impl Opcode for Add {
+    fn name(&self) -> &'static str {
+        "add"
+    }
+
+    fn execute(&self, args: &[i64]) -> Option<Effect> {
+        args[0].checked_add(args[1]).map(Effect::Value)
+    }
+}
+
+impl Opcode for Sub {
+    fn name(&self) -> &'static str {
+        "sub"
+    }
+
+    fn execute(&self, args: &[i64]) -> Option<Effect> {
+        args[0].checked_sub(args[1]).map(Effect::Value)
+    }
+}
+
+impl Opcode for Do {
+    fn name(&self) -> &'static str {
+        "do"
+    }
+
+    fn arity(&self) -> usize {
+        0
+    }
+
+    fn execute(&self, _args: &[i64]) -> Option<Effect> {
+        Some(Effect::Enable)
+    }
+}
+
+impl Opcode for Dont {
+    fn name(&self) -> &'static str {
+        "don't"
+    }
+
+    fn arity(&self) -> usize {
+        0
+    }
+
+    fn execute(&self, _args: &[i64]) -> Option<Effect> {
+        Some(Effect::Disable)
+    }
+}
+
+const PUZZLE_OPS: [&dyn Opcode; 3] = [&Mul, &Do, &Dont];
+const EXTENDED_OPS: [&dyn Opcode; 5] = [&Mul, &Add, &Sub, &Do, &Dont];
+
+/// How deep calls may be nested in operands. Parsing recurses once per level,
+/// so this keeps hostile input from overflowing the stack.
+const MAX_NESTING: usize = 64;
+
+/// Which instructions are recognised and how their operands may be written.
+#[derive(Clone, Copy)]
+pub struct Grammar<'a> {
+    /// Tried in order at every position; the first that parses wins.
+    pub opcodes: &'a [&'a dyn Opcode],
+    /// Longest operand in digits, `None` for any width that fits an `i64`.
+    pub max_digits: Option<usize>,
+    /// Operands may themselves be value instructions, like `mul(add(1,2),3)`,
+    /// up to `MAX_NESTING` levels deep.
+    pub nested: bool,
+}
+
+impl Default for Grammar<'static> {
+    fn default() -> Self {
+        Grammar {
+            opcodes: &PUZZLE_OPS,
+            max_digits: Some(3),
+            nested: false,
         }
     }
+}
+
+/// A recognised instruction together with where it sits in the input.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Instruction {
+    pub offset: usize,
+    pub len: usize,
+    pub name: &'static str,
+    pub effect: Effect,
+}
+
+/// Cursor over the input handed to `Opcode::parse_args`.
+pub struct Parser<'g, 'i> {
+    grammar: &'g Grammar<'g>,
+    input: &'i [u8],
+    pos: usize,
+    /// Calls currently being parsed as operands.
+    depth: usize,
+    /// Some read ran into the end of the input, so more of it could still
+    /// change the outcome.
+    hit_end: bool,
+}
+
+impl Parser<'_, '_> {
+    pub fn literal(&mut self, literal: &[u8]) -> Option<()> {
+        let rest = &self.input[self.pos..];
+        if !rest.starts_with(literal) {
+            self.hit_end |= literal.starts_with(rest);
+            return None;
+        }
+        self.pos += literal.len();
+        Some(())
+    }
+
+    /// A number, or a nested value instruction if the grammar allows those.
+    pub fn operand(&mut self) -> Option<i64> {
+        if let Some(value) = self.number() {
+            return Some(value);
+        }
+        if !self.grammar.nested || self.depth == MAX_NESTING {
+            return None;
+        }
+        self.depth += 1;
+        let call = self.call();
+        self.depth -= 1;
+        match call? {
+            (_, Effect::Value(value)) => Some(value),
+            _ => None,
+        }
+    }
+
+    fn number(&mut self) -> Option<i64> {
+        let limit = self.grammar.max_digits.unwrap_or(usize::MAX);
+        let len = self.input[self.pos..]
+            .iter()
+            .take(limit)
+            .take_while(|b| b.is_ascii_digit())
+            .count();
+        self.hit_end |= len < limit && self.pos + len == self.input.len();
+        if len == 0 {
+            return None;
+        }
+        let value = self.input[self.pos..self.pos + len]
+            .iter()
+            .try_fold(0i64, |acc, b| {
+                acc.checked_mul(10)?.checked_add(i64::from(b - b'0'))
+            })?;
+        self.pos += len;
+        Some(value)
+    }
+
+    fn call(&mut self) -> Option<(&'static str, Effect)> {
+        let start = self.pos;
+        for opcode in self.grammar.opcodes {
+            self.pos = start;
+            let parsed = (|| {
+                self.literal(opcode.name().as_bytes())?;
+                self.literal(b"(")?;
+                let args = opcode.parse_args(self)?;
+                self.literal(b")")?;
+                opcode.execute(&args)
+            })();
+            if let Some(effect) = parsed {
+                return Some((opcode.name(), effect));
+            }
+        }
+        self.pos = start;
+        None
+    }
+}
+
+/// Scans corrupted memory for instructions, skipping everything else. Matches
+/// never overlap: scanning resumes after the end of each one.
+///
+/// Input can be fed in chunks of any size. Bytes that might still start an
+/// instruction once more input arrives are held back until it does.
+struct Tokenizer<'g> {
+    grammar: &'g Grammar<'g>,
+    pending: Vec<u8>,
+    /// Offset of `pending[0]` in the whole input.
+    offset: usize,
+}
+
+impl<'g> Tokenizer<'g> {
+    fn new(grammar: &'g Grammar<'g>) -> Self {
+        Tokenizer {
+            grammar,
+            pending: Vec::new(),
+            offset: 0,
+        }
+    }
+
+    fn feed(&mut self, chunk: &[u8]) -> Vec<Instruction> {
+        self.pending.extend_from_slice(chunk);
+        self.scan(false)
+    }
+
+    fn finish(mut self) -> Vec<Instruction> {
+        self.scan(true)
+    }
+
+    fn scan(&mut self, last: bool) -> Vec<Instruction> {
+        let mut instructions = Vec::new();
+        let mut parser = Parser {
+            grammar: self.grammar,
+            input: &self.pending,
+            pos: 0,
+            depth: 0,
+            hit_end: false,
+        };
+        while parser.pos < parser.input.len() {
+            let start = parser.pos;
+            parser.hit_end = false;
+            let call = parser.call();
+            if parser.hit_end && !last {
+                parser.pos = start;
+                break;
+            }
+            match call {
+                Some((name, effect)) => instructions.push(Instruction {
+                    offset: self.offset + start,
+                    len: parser.pos - start,
+                    name,
+                    effect,
+                }),
+                None => parser.pos = start + 1,
+            }
+        }
+
+        let consumed = parser.pos;
+        self.pending.drain(..consumed);
+        self.offset += consumed;
+        instructions
+    }
+}
+
+fn tokenize(input: &[u8], grammar: &Grammar) -> Vec<Instruction> {
+    let mut tokenizer = Tokenizer::new(grammar);
+    let mut instructions = tokenizer.feed(input);
+    instructions.extend(tokenizer.finish());
+    instructions
+}
+
+// Instructions that only read as one once line breaks are removed, like
+// `mul(3,\n4)`. They don't run, but the listings point them out. Offsets and
+// lengths are in the original input, line breaks included.
+fn split_instructions(input: &[u8], grammar: &Grammar) -> Vec<Instruction> {
+    let (joined, origins): (Vec<u8>, Vec<usize>) = input
+        .iter()
+        .enumerate()
+        .filter(|&(_, &b)| b != b'\n' && b != b'\r')
+        .map(|(i, &b)| (b, i))
+        .unzip();
+    tokenize(&joined, grammar)
+        .into_iter()
+        .filter_map(|instruction| {
+            let start = origins[instruction.offset];
+            let end = origins[instruction.offset + instruction.len - 1] + 1;
+            (end - start != instruction.len).then_some(Instruction {
+                offset: start,
+                len: end - start,
+                ..instruction
+            })
+        })
+        .collect()
+}
+
+/// Interpreter state: whether `mul` and friends are enabled, and the totals
+/// for both parts so far.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+struct Machine {
+    dont: bool,
+    uncorrupted_result: i64,
+    enhanced_result: i64,
+}
+
+impl Machine {
+    // Runs one instruction, returning whether it was enabled when reached. Fails
+    // if a total no longer fits in an i64.
+    fn step(&mut self, instruction: &Instruction) -> Result<bool> {
+        let enabled = !self.dont;
+        let add = |total: i64, value: i64| {
+            total
+                .checked_add(value)
+                .ok_or_else(|| anyhow!("Total overflows i64 at offset {}", instruction.offset))
+        };
+        match instruction.effect {
+            Effect::Value(value) => {
+                self.uncorrupted_result = add(self.uncorrupted_result, value)?;
+                if enabled {
+                    self.enhanced_result = add(self.enhanced_result, value)?;
+                }
+            }
+            Effect::Disable => self.dont = true,
+            Effect::Enable => self.dont = false,
+        }
+        Ok(enabled)
+    }
+
+    fn totals(&self) -> (i64, i64) {
+        (self.uncorrupted_result, self.enhanced_result)
+    }
+}
+
+/// An executed instruction, or one split across lines, for tracing.
+#[derive(Debug, PartialEq)]
+struct TraceStep {
+    instruction: Instruction,
+    /// No `don't()` was in effect when the instruction was reached.
+    enabled: bool,
+    /// The instruction is split across lines, so it did not run.
+    split: bool,
+    /// Totals for both parts after the instruction ran.
+    totals: (i64, i64),
+}
+
+// Runs `instructions`, listing the `split` ones in between where they occur
+fn trace(instructions: &[Instruction], split: &[Instruction]) -> Result<Vec<TraceStep>> {
+    let mut all: Vec<(Instruction, bool)> = instructions
+        .iter()
+        .map(|&instruction| (instruction, false))
+        .chain(split.iter().map(|&instruction| (instruction, true)))
+        .collect();
+    all.sort_by_key(|(instruction, _)| instruction.offset);
+
+    let mut machine = Machine::default();
+    all.into_iter()
+        .map(|(instruction, split)| {
+            Ok(TraceStep {
+                instruction,
+                enabled: if split {
+                    !machine.dont
+                } else {
+                    machine.step(&instruction)?
+                },
+                split,
+                totals: machine.totals(),
+            })
+        })
+        .collect()
+}
+
+// Sum of all values, and of those not disabled by a preceding `don't()`
+fn execute(instructions: &[Instruction]) -> Result<(i64, i64)> {
+    let mut machine = Machine::default();
+    for instruction in instructions {
+        machine.step(instruction)?;
+    }
+    Ok(machine.totals())
+}
+
+// 1-based line and column of a byte offset, given where each line starts
+fn line_col(line_starts: &[usize], offset: usize) -> (usize, usize) {
+    let line = line_starts.partition_point(|&start| start <= offset);
+    (line, offset - line_starts[line - 1] + 1)
+}
 
-    (uncorrupted_result, enhanced_result)
+fn line_starts(input: &[u8]) -> Vec<usize> {
+    std::iter::once(0)
+        .chain(
+            input
+                .iter()
+                .enumerate()
+                .filter(|&(_, &b)| b == b'\n')
+                .map(|(i, _)| i + 1),
+        )
+        .collect()
+}
+
+// Source text of an instruction, with any line breaks inside it escaped
+fn source(input: &[u8], instruction: &Instruction) -> String {
+    String::from_utf8_lossy(&input[instruction.offset..instruction.offset + instruction.len])
+        .replace('\r', "\\r")
+        .replace('\n', "\\n")
+}
+
+// One line per valid instruction: offset, line:column and its source text.
+// Instructions split across lines are listed too, marked as not run.
+fn disassemble(input: &[u8], instructions: &[Instruction], split: &[Instruction]) -> Vec<String> {
+    let starts = line_starts(input);
+    let mut all: Vec<(&Instruction, bool)> = instructions
+        .iter()
+        .map(|instruction| (instruction, false))
+        .chain(split.iter().map(|instruction| (instruction, true)))
+        .collect();
+    all.sort_by_key(|(instruction, _)| instruction.offset);
+    all.into_iter()
+        .map(|(instruction, split)| {
+            let (line, col) = line_col(&starts, instruction.offset);
+            let line = format!(
+                "{:>8}  {:>5}:{:<4}  {}",
+                instruction.offset,
+                line,
+                col,
+                source(input, instruction)
+            );
+            if split {
+                line + "  (split across lines, not run)"
+            } else {
+                line
+            }
+        })
+        .collect()
+}
+
+const CHUNK_SIZE: usize = 64 * 1024;
+
+// Runs the program as it is read, `chunk_size` bytes at a time
+fn execute_stream(
+    mut reader: impl Read,
+    grammar: &Grammar,
+    chunk_size: usize,
+) -> Result<(i64, i64)> {
+    let mut tokenizer = Tokenizer::new(grammar);
+    let mut machine = Machine::default();
+    let mut chunk = vec![0; chunk_size];
+    loop {
+        let read = reader.read(&mut chunk)?;
+        if read == 0 {
+            break;
+        }
+        for instruction in tokenizer.feed(&chunk[..read]) {
+            machine.step(&instruction)?;
+        }
+    }
+    for instruction in tokenizer.finish() {
+        machine.step(&instruction)?;
+    }
+    Ok(machine.totals())
 }
 
 pub fn solve(file_path: &str) -> Result<(i64, i64)> {
-    let program = read_file(file_path)?;
-    Ok(parse_and_execute(&program))
+    execute_stream(File::open(file_path)?, &Grammar::default(), CHUNK_SIZE)
+}
+
+/// Prints the solve result under the puzzle's instruction set, or with
+/// `--extended` also `add` and `sub`. `--max-digits N|any` changes the operand
+/// width and `--nested` allows calls as operands. `--disasm` lists the valid
+/// instructions with their positions and `--trace` also shows whether each one
+/// was enabled and the running totals. Both point out instructions that are
+/// split across line breaks and so do not run.
+pub fn run(input: &str, args: &[String]) -> Result<()> {
+    let mut grammar = Grammar::default();
+    let mut disasm = false;
+    let mut tracing = false;
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--extended" => grammar.opcodes = &EXTENDED_OPS,
+            "--nested" => grammar.nested = true,
+            "--disasm" => disasm = true,
+            "--trace" => tracing = true,
+            "--max-digits" => {
+                grammar.max_digits = match args.next().map(String::as_str) {
+                    Some("any") => None,
+                    Some(n) => Some(n.parse()?),
+                    None => return Err(anyhow!("--max-digits needs a value")),
+                }
+            }
+            _ => return Err(anyhow!("Unknown option: {}", arg)),
+        }
+    }
+
+    let program = fs::read(input)?;
+    let instructions = tokenize(&program, &grammar);
+    let split = if disasm || tracing {
+        split_instructions(&program, &grammar)
+    } else {
+        Vec::new()
+    };
+    if disasm {
+        for line in disassemble(&program, &instructions, &split) {
+            println!("{}", line);
+        }
+    }
+    if tracing {
+        let starts = line_starts(&program);
+        for step in trace(&instructions, &split)? {
+            let (line, col) = line_col(&starts, step.instruction.offset);
+            println!(
+                "{:>8}  {:>5}:{:<4}  {:<24}  {:<8}  {:>12}  {:>12}",
+                step.instruction.offset,
+                line,
+                col,
+                source(&program, &step.instruction),
+                match (step.split, step.enabled) {
+                    (true, _) => "split",
+                    (false, true) => "enabled",
+                    (false, false) => "disabled",
+                },
+                step.totals.0,
+                step.totals.1
+            );
+        }
+    }
+    println!("Day 03 result: {:?}", execute(&instructions)?);
+    Ok(())
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
 
+    const EXAMPLE: &str =
+        "xmul(2,4)&mul[3,7]!^don't()_mul(5,5)+mul(32,64](mul(11,8)undo()?mul(8,5))";
+
     #[test]
     fn test_execute() {
-        let program = String::from(
-            "xmul(2,4)&mul[3,7]!^don't()_mul(5,5)+mul(32,64](mul(11,8)undo()?mul(8,5))",
+        assert_eq!(
+            execute(&tokenize(EXAMPLE.as_bytes(), &Grammar::default())).unwrap(),
+            (161, 48)
         );
-        assert_eq!(parse_and_execute(&program), (161, 48));
+    }
+
+    fn effects(input: &[u8], grammar: &Grammar) -> Vec<Effect> {
+        tokenize(input, grammar)
+            .into_iter()
+            .map(|instruction| instruction.effect)
+            .collect()
+    }
+
+    #[test]
+    fn test_tokenize_offsets() {
+        let instructions: Vec<_> = tokenize(EXAMPLE.as_bytes(), &Grammar::default())
+            .into_iter()
+            .map(|i| (i.offset, i.len, i.name, i.effect))
+            .collect();
+        assert_eq!(
+            instructions,
+            [
+                (1, 8, "mul", Effect::Value(8)),
+                (20, 7, "don't", Effect::Disable),
+                (28, 8, "mul", Effect::Value(25)),
+                (48, 9, "mul", Effect::Value(88)),
+                (59, 4, "do", Effect::Enable),
+                (64, 8, "mul", Effect::Value(40)),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_malformed_instructions() {
+        let grammar = Grammar::default();
+        let ops = |input: &[u8]| effects(input, &grammar);
+        assert_eq!(ops(b"mul(1234,5)mul(123,4)"), [Effect::Value(492)]);
+        assert_eq!(ops(b"mul( 2,3)mul(2,3 )mul(,3)"), []);
+        assert_eq!(ops(b"mmul(2,3)"), [Effect::Value(6)]);
+        assert_eq!(ops(b"mul(2,3"), []);
+        assert_eq!(ops(b"don't()do()don't("), [Effect::Disable, Effect::Enable]);
+        // Bytes that aren't valid UTF-8 are just skipped
+        assert_eq!(ops(b"\xff\xfemul(7,6)\xc3"), [Effect::Value(42)]);
+        // Not in the puzzle's instruction set
+        assert_eq!(ops(b"add(1,2)mul(add(1,2),3)"), []);
+    }
+
+    #[test]
+    fn test_extended_instructions() {
+        let grammar = Grammar {
+            opcodes: &EXTENDED_OPS,
+            ..Grammar::default()
+        };
+        assert_eq!(
+            effects(b"add(1,2)sub(2,5)mul(3,4)", &grammar),
+            [Effect::Value(3), Effect::Value(-3), Effect::Value(12)]
+        );
+        assert_eq!(
+            execute(&tokenize(b"add(1,2)don't()sub(9,1)do()mul(2,2)", &grammar)).unwrap(),
+            (15, 7)
+        );
+    }
+
+    #[test]
+    fn test_operand_width() {
+        let wide = Grammar {
+            max_digits: None,
+            ..Grammar::default()
+        };
+        assert_eq!(effects(b"mul(1234,5)", &wide), [Effect::Value(6170)]);
+        // Operands or products past i64 make the instruction invalid
+        assert_eq!(effects(b"mul(99999999999999999999,1)", &wide), []);
+        assert_eq!(effects(b"mul(9999999999,9999999999)", &wide), []);
+
+        let narrow = Grammar {
+            max_digits: Some(1),
+            ..Grammar::default()
+        };
+        assert_eq!(effects(b"mul(12,3)mul(1,3)", &narrow), [Effect::Value(3)]);
+    }
+
+    #[test]
+    fn test_total_overflow_is_an_error() {
+        let wide = Grammar {
+            max_digits: None,
+            ..Grammar::default()
+        };
+        let input = b"mul(9223372036854775807,1)mul(1,1)";
+        let instructions = tokenize(input, &wide);
+        assert_eq!(instructions.len(), 2);
+        let err = execute(&instructions).unwrap_err();
+        assert_eq!(err.to_string(), "Total overflows i64 at offset 26");
+        assert!(trace(&instructions, &[]).is_err());
+        assert!(execute_stream(&input[..], &wide, CHUNK_SIZE).is_err());
+    }
+
+    #[test]
+    fn test_nested_calls() {
+        let grammar = Grammar {
+            opcodes: &EXTENDED_OPS,
+            nested: true,
+            ..Grammar::default()
+        };
+        let instructions = tokenize(b"xmul(add(1,2),sub(10,mul(2,3)))!", &grammar);
+        assert_eq!(
+            instructions,
+            [Instruction {
+                offset: 1,
+                len: 30,
+                name: "mul",
+                effect: Effect::Value(12),
+            }]
+        );
+        // Control instructions have no value to pass on
+        assert_eq!(effects(b"mul(do(),2)", &grammar), [Effect::Enable]);
+        // A broken nested call leaves the inner instruction to match alone
+        assert_eq!(effects(b"mul(add(1,2),x)", &grammar), [Effect::Value(3)]);
+    }
+
+    #[test]
+    fn test_nesting_depth_limit() {
+        let grammar = Grammar {
+            nested: true,
+            ..Grammar::default()
+        };
+        let nest = |depth: usize| {
+            let mut input = "mul(".repeat(depth + 1);
+            input.push('2');
+            input.push_str(&",1)".repeat(depth + 1));
+            input.into_bytes()
+        };
+
+        let instructions = tokenize(&nest(MAX_NESTING), &grammar);
+        assert_eq!(instructions.len(), 1);
+        assert_eq!(instructions[0].offset, 0);
+        assert_eq!(instructions[0].effect, Effect::Value(2));
+
+        // Too deep to parse from the outside: only the innermost calls within
+        // the limit match, without running out of stack
+        let depth = 10_000;
+        let instructions = tokenize(&nest(depth), &grammar);
+        assert_eq!(instructions.len(), 1);
+        assert_eq!(instructions[0].offset, 4 * (depth - MAX_NESTING));
+        assert_eq!(
+            instructions[0].len,
+            4 * (MAX_NESTING + 1) + 1 + 3 * (MAX_NESTING + 1)
+        );
+    }
+
+    #[test]
+    fn test_trace() {
+        let steps = trace(&tokenize(EXAMPLE.as_bytes(), &Grammar::default()), &[]).unwrap();
+        let summary: Vec<_> = steps
+            .iter()
+            .map(|step| (step.instruction.offset, step.enabled, step.totals))
+            .collect();
+        assert_eq!(
+            summary,
+            [
+                (1, true, (8, 8)),
+                (20, true, (8, 8)),
+                (28, false, (33, 8)),
+                (48, false, (121, 8)),
+                (59, false, (121, 8)),
+                (64, true, (161, 48)),
+            ]
+        );
+    }
+
+    const ACROSS_LINES: &[u8] = b"mul(1,2)\nxmul(3,\n4)do()\r\ndon't()mu\nl(5,6)";
+
+    #[test]
+    fn test_disassemble_across_lines() {
+        let grammar = Grammar::default();
+        let instructions = tokenize(ACROSS_LINES, &grammar);
+        let split = split_instructions(ACROSS_LINES, &grammar);
+        assert_eq!(
+            split.iter().map(|i| (i.offset, i.len)).collect::<Vec<_>>(),
+            [(10, 9), (32, 9)]
+        );
+        assert_eq!(
+            disassemble(ACROSS_LINES, &instructions, &split),
+            [
+                "       0      1:1     mul(1,2)",
+                "      10      2:2     mul(3,\\n4)  (split across lines, not run)",
+                "      19      3:3     do()",
+                "      25      4:1     don't()",
+                "      32      4:8     mu\\nl(5,6)  (split across lines, not run)",
+            ]
+        );
+    }
+
+    #[test]
+    fn test_trace_across_lines() {
+        let grammar = Grammar::default();
+        let steps = trace(
+            &tokenize(ACROSS_LINES, &grammar),
+            &split_instructions(ACROSS_LINES, &grammar),
+        )
+        .unwrap();
+        let summary: Vec<_> = steps
+            .iter()
+            .map(|step| {
+                (
+                    step.instruction.offset,
+                    step.split,
+                    step.enabled,
+                    step.totals,
+                )
+            })
+            .collect();
+        // Split instructions are listed where they occur but add nothing
+        assert_eq!(
+            summary,
+            [
+                (0, false, true, (2, 2)),
+                (10, true, true, (2, 2)),
+                (19, false, true, (2, 2)),
+                (25, false, true, (2, 2)),
+                (32, true, false, (2, 2)),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_split_at_every_offset() {
+        let input = EXAMPLE.as_bytes();
+        let grammar = Grammar::default();
+        let expected = tokenize(input, &grammar);
+        for split in 0..=input.len() {
+            let mut tokenizer = Tokenizer::new(&grammar);
+            let mut instructions = tokenizer.feed(&input[..split]);
+            instructions.extend(tokenizer.feed(&input[split..]));
+            instructions.extend(tokenizer.finish());
+            assert_eq!(instructions, expected, "split at {}", split);
+        }
+    }
+
+    #[test]
+    fn test_stream_in_small_chunks() {
+        let grammar = Grammar::default();
+        for chunk_size in 1..=EXAMPLE.len() {
+            assert_eq!(
+                execute_stream(EXAMPLE.as_bytes(), &grammar, chunk_size).unwrap(),
+                (161, 48),
+                "chunks of {}",
+                chunk_size
+            );
+        }
+
+        // Nested calls and wide operands can be held back for a while
+        let grammar = Grammar {
+            opcodes: &EXTENDED_OPS,
+            max_digits: None,
+            nested: true,
+        };
+        let input = b"don't()mul(add(1,2),sub(100000,mul(2,3)))do()mul(12345,2)mul(add(1,";
+        let expected = execute(&tokenize(input, &grammar)).unwrap();
+        assert_eq!(expected, (324_672, 24_690));
+        for chunk_size in 1..=input.len() {
+            assert_eq!(
+                execute_stream(&input[..], &grammar, chunk_size).unwrap(),
+                expected,
+                "chunks of {}",
+                chunk_size
+            );
+        }
     }
 }
@@ -17,78 +17,232 @@ fn load_grid_from_file(filename: &str) -> io::Result<Vec<Vec<char>>> {
     Ok(grid)
 }
 
-fn find_word(grid: &[Vec<char>], word: &str) -> usize {
-    let directions = [
-        (0, 1),  // Right
-        (1, 0),  // Down
-        (1, 1),  // Diagonal down-right
-        (1, -1), // Diagonal down-left
-    ];
-
-    let rows = grid.len();
-    let cols = grid[0].len();
-    let word_chars: Vec<char> = word.chars().collect();
-    let word_len = word_chars.len();
-
-    let mut count = 0;
-
-    for r in 0..rows {
-        for c in 0..cols {
-            for &(dr, dc) in &directions {
-                if (0..word_len).all(|i| {
-                    let x = r as isize + dr * i as isize;
-                    let y = c as isize + dc * i as isize;
-                    x >= 0
-                        && x < rows as isize
-                        && y >= 0
-                        && y < cols as isize
-                        && grid[x as usize][y as usize] == word_chars[i]
-                }) {
-                    count += 1;
+/// A small pattern of cells to look for in the grid; `None` matches any cell.
+#[derive(Clone, Debug, PartialEq)]
+struct Stencil {
+    cells: Vec<Vec<Option<char>>>,
+}
+
+impl Stencil {
+    /// Rows separated by `/`, with `.` as a wildcard, like `M.S/.A./M.S`. Rows
+    /// must all have the same, non-zero length and some cell must not be a
+    /// wildcard.
+    fn parse(spec: &str) -> io::Result<Self> {
+        let invalid = |reason: &str| {
+            io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!("Invalid stencil {:?}: {}", spec, reason),
+            )
+        };
+        let cells: Vec<Vec<Option<char>>> = spec
+            .split('/')
+            .map(|row| row.chars().map(|ch| (ch != '.').then_some(ch)).collect())
+            .collect();
+        if cells.iter().any(Vec::is_empty) {
+            return Err(invalid("empty row"));
+        }
+        if cells.iter().any(|row| row.len() != cells[0].len()) {
+            return Err(invalid("rows differ in length"));
+        }
+        if cells.iter().flatten().all(Option::is_none) {
+            return Err(invalid("nothing but wildcards"));
+        }
+        Ok(Stencil { cells })
+    }
+
+    /// The word laid out left to right.
+    fn word(word: &str) -> Self {
+        Stencil {
+            cells: vec![word.chars().map(Some).collect()],
+        }
+    }
+
+    /// The word laid out from the top left corner to the bottom right one.
+    fn diagonal(word: &str) -> Self {
+        let len = word.chars().count();
+        let cells = word
+            .chars()
+            .enumerate()
+            .map(|(i, ch)| (0..len).map(|j| (i == j).then_some(ch)).collect())
+            .collect();
+        Stencil { cells }
+    }
+
+    /// The word along both diagonals, crossing in the middle, each read from
+    /// the left.
+    fn cross(word: &str) -> Self {
+        let mut cross = Self::diagonal(word);
+        let len = cross.height();
+        for (i, ch) in word.chars().enumerate() {
+            cross.cells[len - 1 - i][i] = Some(ch);
+        }
+        cross
+    }
+
+    fn height(&self) -> usize {
+        self.cells.len()
+    }
+
+    fn width(&self) -> usize {
+        self.cells.iter().map(Vec::len).max().unwrap_or(0)
+    }
+
+    fn cell(&self, r: usize, c: usize) -> Option<char> {
+        self.cells
+            .get(r)
+            .and_then(|row| row.get(c).copied().flatten())
+    }
+
+    // Quarter turn clockwise
+    fn rotate(&self) -> Self {
+        let height = self.height();
+        let cells = (0..self.width())
+            .map(|r| (0..height).map(|c| self.cell(height - 1 - c, r)).collect())
+            .collect();
+        Stencil { cells }
+    }
+
+    // Mirrored left to right
+    fn reflect(&self) -> Self {
+        let width = self.width();
+        let cells = (0..self.height())
+            .map(|r| (0..width).map(|c| self.cell(r, width - 1 - c)).collect())
+            .collect();
+        Stencil { cells }
+    }
+
+    /// The distinct stencils among all rotations and reflections, starting with
+    /// this one.
+    fn orientations(&self) -> Vec<Stencil> {
+        let mut orientations: Vec<Stencil> = Vec::new();
+        for mut stencil in [self.clone(), self.reflect()] {
+            for _ in 0..4 {
+                if !orientations.contains(&stencil) {
+                    orientations.push(stencil.clone());
                 }
+                stencil = stencil.rotate();
             }
         }
+        orientations
     }
 
-    count
-}
+    fn matches_at(&self, grid: &[Vec<char>], r: usize, c: usize) -> bool {
+        self.cells.iter().enumerate().all(|(dr, row)| {
+            row.iter().enumerate().all(|(dc, cell)| {
+                let Some(found) = grid.get(r + dr).and_then(|line| line.get(c + dc)) else {
+                    return false;
+                };
+                cell.is_none_or(|ch| ch == *found)
+            })
+        })
+    }
 
-fn find_x_shaped_mas(grid: &[Vec<char>]) -> usize {
-    let rows = grid.len();
-    let cols = grid[0].len();
-    let mut count = 0;
-
-    for r in 1..rows - 1 {
-        for c in 1..cols - 1 {
-            // Check if the center is 'A'
-            if grid[r][c] == 'A' {
-                // Get diagonal values
-                let tl = grid[r - 1][c - 1];
-                let tr = grid[r - 1][c + 1];
-                let bl = grid[r + 1][c - 1];
-                let br = grid[r + 1][c + 1];
-
-                // Check for MAS or SAM on both diagonals
-                let valid_tl_br = (tl == 'M' && br == 'S') || (tl == 'S' && br == 'M');
-                let valid_tr_bl = (tr == 'M' && bl == 'S') || (tr == 'S' && bl == 'M');
-
-                // X is valid only if both diagonals are valid
-                if valid_tl_br && valid_tr_bl {
-                    count += 1;
+    /// Top left corners of every place the stencil fits as it is.
+    fn find(&self, grid: &[Vec<char>]) -> Vec<(usize, usize)> {
+        let (height, width) = (self.height(), self.width());
+        let mut positions = Vec::new();
+        let grid_width = grid.iter().map(Vec::len).max().unwrap_or(0);
+        for r in 0..(grid.len() + 1).saturating_sub(height) {
+            for c in 0..(grid_width + 1).saturating_sub(width) {
+                if self.matches_at(grid, r, c) {
+                    positions.push((r, c));
                 }
             }
         }
+        positions
+    }
+}
+
+/// Where a stencil was found: the top left corner and which of
+/// `Stencil::orientations` fits there.
+#[derive(Debug, PartialEq)]
+struct Match {
+    row: usize,
+    col: usize,
+    orientation: usize,
+}
+
+fn find_pattern(grid: &[Vec<char>], stencil: &Stencil) -> Vec<Match> {
+    stencil
+        .orientations()
+        .iter()
+        .enumerate()
+        .flat_map(|(orientation, stencil)| {
+            stencil.find(grid).into_iter().map(move |(row, col)| Match {
+                row,
+                col,
+                orientation,
+            })
+        })
+        .collect()
+}
+
+// Occurrences of the word in any of the 8 directions. For a one-letter word
+// the straight and diagonal stencils are the same, so it is counted once.
+fn find_word(grid: &[Vec<char>], word: &str) -> usize {
+    let mut orientations = Stencil::word(word).orientations();
+    for stencil in Stencil::diagonal(word).orientations() {
+        if !orientations.contains(&stencil) {
+            orientations.push(stencil);
+        }
     }
+    orientations
+        .iter()
+        .map(|stencil| stencil.find(grid).len())
+        .sum()
+}
 
-    count
+fn find_x_shaped_mas(grid: &[Vec<char>]) -> usize {
+    find_pattern(grid, &Stencil::cross("MAS")).len()
 }
 
 pub fn solve(file_path: &str) -> io::Result<(usize, usize)> {
     let grid = load_grid_from_file(file_path)?;
-    let xmas_count = find_word(&grid, "XMAS");
-    let samx_count = find_word(&grid, "SAMX");
-    let mas = find_x_shaped_mas(&grid);
-    Ok((xmas_count + samx_count, mas))
+    Ok((find_word(&grid, "XMAS"), find_x_shaped_mas(&grid)))
+}
+
+/// Prints the solve result, or with `--pattern SPEC` how often that stencil
+/// occurs in any orientation (see `Stencil::parse`). `--positions` also lists
+/// where.
+pub fn run(input: &str, args: &[String]) -> io::Result<()> {
+    let mut pattern = None;
+    let mut positions = false;
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--pattern" => {
+                let spec = args.next().ok_or_else(|| {
+                    io::Error::new(io::ErrorKind::InvalidInput, "--pattern needs a value")
+                })?;
+                pattern = Some(Stencil::parse(spec)?);
+            }
+            "--positions" => positions = true,
+            _ => {
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidInput,
+                    format!("Unknown option: {}", arg),
+                ))
+            }
+        }
+    }
+
+    let Some(stencil) = pattern else {
+        println!("Day 04 result: {:?}", solve(input)?);
+        return Ok(());
+    };
+    let grid = load_grid_from_file(input)?;
+    let matches = find_pattern(&grid, &stencil);
+    if positions {
+        for m in &matches {
+            println!("({}, {}) orientation {}", m.row, m.col, m.orientation);
+        }
+    }
+    println!(
+        "{} matches in {} orientations",
+        matches.len(),
+        stencil.orientations().len()
+    );
+    Ok(())
 }
 
 #[cfg(test)]
@@ -167,7 +321,7 @@ mod tests {
             vec!['M', 'X', 'M', 'X', 'A', 'X', 'M', 'A', 'S', 'X'],
         ];
 
-        let result = find_word(&grid, "XMAS") + find_word(&grid, "SAMX");
+        let result = find_word(&grid, "XMAS");
         assert_eq!(result, 18);
     }
 
@@ -201,4 +355,97 @@ mod tests {
         let result = find_x_shaped_mas(&grid);
         assert_eq!(result, 0);
     }
+
+    #[test]
+    fn test_stencil_orientations() {
+        let x_mas = Stencil::parse("M.S/.A./M.S").unwrap();
+        assert_eq!(Stencil::cross("MAS"), x_mas);
+        let specs: Vec<_> = x_mas
+            .orientations()
+            .iter()
+            .map(|stencil| {
+                stencil
+                    .cells
+                    .iter()
+                    .map(|row| {
+                        row.iter()
+                            .map(|cell| cell.unwrap_or('.'))
+                            .collect::<String>()
+                    })
+                    .collect::<Vec<_>>()
+                    .join("/")
+            })
+            .collect();
+        assert_eq!(
+            specs,
+            ["M.S/.A./M.S", "M.M/.A./S.S", "S.M/.A./S.M", "S.S/.A./M.M"]
+        );
+
+        assert_eq!(Stencil::word("XMAS").orientations().len(), 4);
+        assert_eq!(Stencil::diagonal("XMAS").orientations().len(), 4);
+        // Symmetric under every rotation and reflection
+        assert_eq!(
+            Stencil::parse(".A./AAA/.A.").unwrap().orientations().len(),
+            1
+        );
+        // An L shape has all 8
+        assert_eq!(Stencil::parse("A./A./AB").unwrap().orientations().len(), 8);
+    }
+
+    #[test]
+    fn test_find_pattern_positions() {
+        let grid = vec![
+            vec!['A', '.', '.', '.'],
+            vec!['A', '.', 'B', 'A'],
+            vec!['A', 'B', '.', 'A'],
+            vec!['.', '.', '.', 'A'],
+        ];
+        let matches = find_pattern(&grid, &Stencil::parse("A./A./AB").unwrap());
+        assert_eq!(
+            matches,
+            [
+                Match {
+                    row: 0,
+                    col: 0,
+                    orientation: 0,
+                },
+                Match {
+                    row: 1,
+                    col: 2,
+                    orientation: 2,
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn test_patterns_on_ragged_grid() {
+        let grid = vec![vec!['X', 'M', 'A', 'S'], vec!['M'], vec!['A', '.', 'A']];
+        assert_eq!(find_word(&grid, "XMA"), 2);
+        assert_eq!(
+            find_pattern(&grid, &Stencil::parse("A.A").unwrap()).len(),
+            1
+        );
+    }
+
+    #[test]
+    fn test_invalid_stencils() {
+        for spec in ["", "/", "AB//CD", "AB/", "A./A./ABC", "A/BC", ".", "../.."] {
+            let err = Stencil::parse(spec).unwrap_err();
+            assert_eq!(err.kind(), io::ErrorKind::InvalidInput, "{:?}", spec);
+        }
+        let err = run(
+            "inputs/day04.txt",
+            &["--pattern".to_string(), String::new()],
+        )
+        .unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidInput);
+    }
+
+    #[test]
+    fn test_find_single_letter() {
+        let grid = vec![vec!['X', 'X'], vec!['X', 'M']];
+        assert_eq!(find_word(&grid, "X"), 3);
+        assert_eq!(find_word(&grid, "XM"), 3);
+    }
 }
@@ -1,6 +1,9 @@
 use rayon::prelude::*;
 use rustc_hash::{FxHashMap, FxHashSet};
+use std::collections::hash_map::Entry;
+use std::fmt;
 use std::fs::File;
+use std::hash::Hash;
 use std::io::{self, BufRead};
 use std::path::Path;
 
@@ -11,77 +14,502 @@ const DIRECTIONS: [(i32, i32); 4] = [
     (0, -1), // up
 ];
 
+const GUARDS: [char; 4] = ['>', 'v', '<', '^'];
+
+/// Problems with a map that make it impossible to patrol.
+#[derive(Debug, PartialEq)]
+pub enum MapError {
+    NoGuard,
+    MultipleGuards(Vec<(i32, i32)>),
+    UnknownChar {
+        ch: char,
+        x: usize,
+        y: usize,
+    },
+    RaggedLine {
+        line: usize,
+        len: usize,
+        expected: usize,
+    },
+    /// The guard is stuck in a loop before any obstacle is added.
+    GuardNeverLeaves,
+}
+
+impl fmt::Display for MapError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            MapError::NoGuard => write!(f, "No guard found on the map"),
+            MapError::MultipleGuards(positions) => {
+                write!(
+                    f,
+                    "Expected one guard, found {} at {:?}",
+                    positions.len(),
+                    positions
+                )
+            }
+            MapError::UnknownChar { ch, x, y } => {
+                write!(f, "Unknown character {:?} at ({}, {})", ch, x, y)
+            }
+            MapError::RaggedLine {
+                line,
+                len,
+                expected,
+            } => write!(f, "Line {} has {} cells, expected {}", line, len, expected),
+            MapError::GuardNeverLeaves => write!(f, "The guard never leaves the map"),
+        }
+    }
+}
+
+impl std::error::Error for MapError {}
+
+impl From<MapError> for io::Error {
+    fn from(e: MapError) -> Self {
+        io::Error::new(io::ErrorKind::InvalidData, e)
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Turn {
+    Right,
+    Left,
+}
+
+impl Turn {
+    fn apply(self, dir: usize) -> usize {
+        match self {
+            Turn::Right => (dir + 1) % 4,
+            Turn::Left => (dir + 3) % 4,
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Edges {
+    /// Guards walking off the map leave it.
+    Exit,
+    /// The map is a torus: leaving one side re-enters on the opposite one.
+    Wrap,
+}
+
+/// How guards react to obstacles and map edges.
+#[derive(Clone, Debug)]
+pub struct PatrolRules {
+    pub turn: Turn,
+    pub obstacles: Vec<char>,
+    pub edges: Edges,
+}
+
+impl Default for PatrolRules {
+    fn default() -> Self {
+        PatrolRules {
+            turn: Turn::Right,
+            obstacles: vec!['#'],
+            edges: Edges::Exit,
+        }
+    }
+}
+
+enum Move {
+    Leave,
+    Turn(usize),
+    Advance(i32, i32),
+}
+
+impl PatrolRules {
+    fn turn(&self, dir: usize) -> usize {
+        self.turn.apply(dir)
+    }
+
+    // What a guard on `pos` facing `dir` does next
+    fn next_move(
+        &self,
+        map: &[Vec<char>],
+        pos: (i32, i32),
+        dir: usize,
+        obstacle: Option<(i32, i32)>,
+    ) -> Move {
+        let height = map.len() as i32;
+        let width = map.first().map_or(0, |row| row.len()) as i32;
+        let (dx, dy) = DIRECTIONS[dir];
+        let mut new_x = pos.0 + dx;
+        let mut new_y = pos.1 + dy;
+
+        // Check if we are out of bounds
+        if new_x < 0 || new_y < 0 || new_x >= width || new_y >= height {
+            match self.edges {
+                Edges::Exit => return Move::Leave,
+                Edges::Wrap => {
+                    new_x = new_x.rem_euclid(width);
+                    new_y = new_y.rem_euclid(height);
+                }
+            }
+        }
+
+        // Check if we hit a wall
+        if self
+            .obstacles
+            .contains(&map[new_y as usize][new_x as usize])
+            || Some((new_x, new_y)) == obstacle
+        {
+            return Move::Turn(self.turn(dir));
+        }
+
+        Move::Advance(new_x, new_y)
+    }
+}
+
+// States already passed through, by the step they were first reached at. Shared
+// by single-guard walks and multi-guard patrols to spot loops.
+struct History<S>(FxHashMap<S, usize>);
+
+impl<S: Eq + Hash> History<S> {
+    fn new() -> Self {
+        History(FxHashMap::default())
+    }
+
+    // Records `state` as reached at `step`, or returns the step it was first
+    // reached at if it has been seen before
+    fn revisit(&mut self, state: S, step: usize) -> Option<usize> {
+        match self.0.entry(state) {
+            Entry::Occupied(entry) => Some(*entry.get()),
+            Entry::Vacant(entry) => {
+                entry.insert(step);
+                None
+            }
+        }
+    }
+}
+
+/// How a patrol of one or more guards moving simultaneously ends.
+#[derive(Debug, PartialEq)]
+pub enum PatrolOutcome {
+    /// Every guard has left the map after this many ticks.
+    Exited { ticks: usize },
+    /// The guards are back in the state they had at `start_tick`.
+    Loop { start_tick: usize, period: usize },
+    /// Two guards ended up on the same cell, or walked through each other.
+    Collision {
+        tick: usize,
+        position: (i32, i32),
+        guards: (usize, usize),
+    },
+}
+
 fn parse_input(filename: &str) -> io::Result<Vec<Vec<char>>> {
     let path = Path::new(filename);
     let file = File::open(&path)?;
-    let reader = io::BufReader::new(file);
+    parse_map(io::BufReader::new(file))
+}
 
-    let mut result = Vec::new();
+fn parse_map(reader: impl BufRead) -> io::Result<Vec<Vec<char>>> {
+    let mut result: Vec<Vec<char>> = Vec::new();
 
-    for line in reader.lines() {
-        let line = line?;
-        result.push(line.chars().collect());
+    for (y, line) in reader.lines().enumerate() {
+        let row: Vec<char> = line?.chars().collect();
+        if let Some(first) = result.first() {
+            if row.len() != first.len() {
+                return Err(MapError::RaggedLine {
+                    line: y + 1,
+                    len: row.len(),
+                    expected: first.len(),
+                }
+                .into());
+            }
+        }
+        result.push(row);
     }
 
     Ok(result)
 }
 
+/// A loop closed by placing an extra obstacle in the guard's way.
+#[derive(Debug)]
+pub struct LoopReport {
+    pub obstacle: (i32, i32),
+    /// First state on the cycle as `(x, y, dir)`.
+    pub cycle_start: (i32, i32, usize),
+    /// Step at which the guard first reaches `cycle_start`.
+    pub cycle_start_step: usize,
+    pub cycle_len: usize,
+    /// Cells on the cycle, in the order the guard visits them.
+    pub cycle_cells: Vec<(i32, i32)>,
+    /// Step at which the guard is back in `cycle_start`.
+    pub closing_step: usize,
+}
+
 pub fn walk_map(
-    map: &Vec<Vec<char>>,
+    map: &[Vec<char>],
     start: (i32, i32),
-    mut dir: usize,
+    dir: usize,
     obstacle: Option<(i32, i32)>,
+    rules: &PatrolRules,
 ) -> Option<Vec<(i32, i32, usize)>> {
+    match walk(map, start, dir, obstacle, rules) {
+        (path, None) => Some(path),
+        (_, Some(_)) => None, // Cycle detected
+    }
+}
+
+// Steps the guard cell by cell. Returns the path taken and, if the guard ends up
+// in a loop, the step at which the repeated state was first seen.
+fn walk(
+    map: &[Vec<char>],
+    start: (i32, i32),
+    mut dir: usize,
+    obstacle: Option<(i32, i32)>,
+    rules: &PatrolRules,
+) -> (Vec<(i32, i32, usize)>, Option<usize>) {
     let mut path = Vec::new();
     let mut current = start;
-    let mut visited = FxHashSet::default();
+    let mut history = History::new();
+    let mut turns = 0;
     path.push((current.0, current.1, dir));
-    visited.insert((current.0, current.1, dir));
+    history.revisit((current.0, current.1, dir), 0);
 
     loop {
-        let (dx, dy) = DIRECTIONS[dir];
-        let new_x = current.0 + dx;
-        let new_y = current.1 + dy;
+        match rules.next_move(map, current, dir, obstacle) {
+            Move::Leave => break,
+            Move::Turn(next) => {
+                dir = next;
+                turns += 1;
+                // Boxed in on every side, the guard spins on the spot forever
+                if turns == 4 {
+                    let step = path.len() - 1;
+                    return (path, Some(step));
+                }
+            }
+            Move::Advance(x, y) => {
+                turns = 0;
+                current = (x, y);
+                if let Some(step) = history.revisit((x, y, dir), path.len()) {
+                    return (path, Some(step));
+                }
+                path.push((current.0, current.1, dir));
+            }
+        }
+    }
 
-        // Check if we are out of bounds
-        if new_x < 0 || new_y < 0 || new_x >= map.len() as i32 || new_y >= map[0].len() as i32 {
-            break;
+    (path, None)
+}
+
+pub fn find_loop(
+    map: &[Vec<char>],
+    start: (i32, i32),
+    dir: usize,
+    obstacle: (i32, i32),
+    rules: &PatrolRules,
+) -> Option<LoopReport> {
+    let (path, cycle_start_step) = walk(map, start, dir, Some(obstacle), rules);
+    let cycle_start_step = cycle_start_step?;
+
+    let mut seen = FxHashSet::default();
+    let cycle_cells = path[cycle_start_step..]
+        .iter()
+        .map(|&(x, y, _)| (x, y))
+        .filter(|cell| seen.insert(*cell))
+        .collect();
+
+    Some(LoopReport {
+        obstacle,
+        cycle_start: path[cycle_start_step],
+        cycle_start_step,
+        cycle_len: path.len() - cycle_start_step,
+        cycle_cells,
+        closing_step: path.len(),
+    })
+}
+
+/// Every guard on the map as `(x, y, dir)`, in reading order.
+pub fn starting_points(map: &[Vec<char>]) -> Vec<(i32, i32, usize)> {
+    let mut guards = Vec::new();
+    for (y, row) in map.iter().enumerate() {
+        for (x, &ch) in row.iter().enumerate() {
+            if let Some(dir) = GUARDS.iter().position(|&guard| guard == ch) {
+                guards.push((x as i32, y as i32, dir));
+            }
+        }
+    }
+    guards
+}
+
+/// Moves every guard on the map at once, one action (turn or step) per tick,
+/// until they have all left, run into each other or repeat a joint state.
+pub fn patrol(map: &[Vec<char>], rules: &PatrolRules) -> PatrolOutcome {
+    let mut guards: Vec<Option<(i32, i32, usize)>> =
+        starting_points(map).into_iter().map(Some).collect();
+    let mut history = History::new();
+    history.revisit(guards.clone(), 0);
+
+    let mut tick = 0;
+    loop {
+        tick += 1;
+        let previous = guards.clone();
+        for guard in guards.iter_mut() {
+            if let Some((x, y, dir)) = *guard {
+                *guard = match rules.next_move(map, (x, y), dir, None) {
+                    Move::Leave => None,
+                    Move::Turn(dir) => Some((x, y, dir)),
+                    Move::Advance(x, y) => Some((x, y, dir)),
+                };
+            }
         }
 
-        // Check if we hit a wall
-        if map[new_y as usize][new_x as usize] == '#' || Some((new_x, new_y)) == (obstacle) {
-            dir = (dir + 1) % 4;
-            continue;
+        for (i, guard) in guards.iter().enumerate() {
+            for (j, other) in guards.iter().enumerate().skip(i + 1) {
+                let (Some((x, y, _)), Some((ox, oy, _))) = (guard, other) else {
+                    continue;
+                };
+                let swapped = matches!(
+                    (previous[i], previous[j]),
+                    (Some((px, py, _)), Some((pox, poy, _)))
+                        if (px, py) == (*ox, *oy) && (pox, poy) == (*x, *y)
+                );
+                if (x, y) == (ox, oy) || swapped {
+                    return PatrolOutcome::Collision {
+                        tick,
+                        position: (*x, *y),
+                        guards: (i, j),
+                    };
+                }
+            }
         }
 
-        current = (new_x, new_y);
-        if visited.contains(&(current.0, current.1, dir)) {
-            return None; // Cycle detected
+        if guards.iter().all(Option::is_none) {
+            return PatrolOutcome::Exited { ticks: tick };
+        }
+        if let Some(start_tick) = history.revisit(guards.clone(), tick) {
+            return PatrolOutcome::Loop {
+                start_tick,
+                period: tick - start_tick,
+            };
         }
-        path.push((current.0, current.1, dir));
-        visited.insert((current.0, current.1, dir));
     }
-
-    Some(path)
 }
 
-pub fn starting_point(map: &Vec<Vec<char>>) -> (i32, i32, usize) {
+/// Checks that every cell is free, an obstacle under `rules` or a guard.
+pub fn validate_map(map: &[Vec<char>], rules: &PatrolRules) -> Result<(), MapError> {
     for (y, row) in map.iter().enumerate() {
         for (x, &ch) in row.iter().enumerate() {
-            let dir = match ch {
-                '>' => 0,
-                'v' => 1,
-                '<' => 2,
-                '^' => 3,
-                _ => continue,
+            if ch != '.' && !GUARDS.contains(&ch) && !rules.obstacles.contains(&ch) {
+                return Err(MapError::UnknownChar { ch, x, y });
+            }
+        }
+    }
+    Ok(())
+}
+
+/// The single guard of a puzzle map.
+pub fn starting_point(map: &[Vec<char>]) -> Result<(i32, i32, usize), MapError> {
+    match starting_points(map).as_slice() {
+        [] => Err(MapError::NoGuard),
+        [guard] => Ok(*guard),
+        guards => Err(MapError::MultipleGuards(
+            guards.iter().map(|&(x, y, _)| (x, y)).collect(),
+        )),
+    }
+}
+
+// For every cell and direction, the cell the guard stops on before the next
+// obstacle, or `None` if it walks off the map. Lets loop detection jump from turn
+// to turn instead of stepping through every cell. Only maps the guard can leave
+// have such an edge, so wrapping rules are not supported.
+struct JumpTable {
+    width: usize,
+    height: usize,
+    turn: Turn,
+    jumps: Vec<[Option<u32>; 4]>,
+}
+
+impl JumpTable {
+    fn new(map: &[Vec<char>], rules: &PatrolRules) -> Self {
+        debug_assert_eq!(rules.edges, Edges::Exit);
+        let height = map.len();
+        let width = map.first().map_or(0, |row| row.len());
+        let mut jumps = vec![[None; 4]; width * height];
+
+        for (dir, &(dx, dy)) in DIRECTIONS.iter().enumerate() {
+            // Visit cells so that the neighbour ahead is always resolved first
+            let xs: Vec<usize> = if dx > 0 {
+                (0..width).rev().collect()
+            } else {
+                (0..width).collect()
+            };
+            let ys: Vec<usize> = if dy > 0 {
+                (0..height).rev().collect()
+            } else {
+                (0..height).collect()
+            };
+            for &y in &ys {
+                for &x in &xs {
+                    let next_x = x as i32 + dx;
+                    let next_y = y as i32 + dy;
+                    if next_x < 0 || next_y < 0 || next_x >= width as i32 || next_y >= height as i32
+                    {
+                        continue;
+                    }
+                    let (next_x, next_y) = (next_x as usize, next_y as usize);
+                    jumps[y * width + x][dir] = if rules.obstacles.contains(&map[next_y][next_x]) {
+                        Some((y * width + x) as u32)
+                    } else {
+                        jumps[next_y * width + next_x][dir]
+                    };
+                }
+            }
+        }
+
+        JumpTable {
+            width,
+            height,
+            turn: rules.turn,
+            jumps,
+        }
+    }
+
+    // Runs the guard from `start` with an extra obstacle that is not part of the
+    // table, recording every stop in a bitset of (cell, direction) states.
+    fn loops(&self, start: (i32, i32), mut dir: usize, obstacle: (i32, i32)) -> bool {
+        let mut visited = vec![0u64; (self.width * self.height * 4).div_ceil(64)];
+        let (mut x, mut y) = start;
+
+        loop {
+            let stop = self.jumps[y as usize * self.width + x as usize][dir].map(|cell| {
+                (
+                    cell as i32 % self.width as i32,
+                    cell as i32 / self.width as i32,
+                )
+            });
+            let (dx, dy) = DIRECTIONS[dir];
+
+            // Distance to the extra obstacle if it lies ahead on this line
+            let ahead = match (dx, dy) {
+                (0, _) if obstacle.0 == x => (obstacle.1 - y) * dy,
+                (_, 0) if obstacle.1 == y => (obstacle.0 - x) * dx,
+                _ => 0,
+            };
+            let reach = stop.map(|(sx, sy)| (sx - x) * dx + (sy - y) * dy);
+
+            (x, y) = match reach {
+                Some(reach) if ahead <= 0 || ahead > reach + 1 => stop.unwrap(),
+                _ if ahead > 0 => (x + dx * (ahead - 1), y + dy * (ahead - 1)),
+                _ => return false,
             };
-            return (x as i32, y as i32, dir);
+
+            let state = (y as usize * self.width + x as usize) * 4 + dir;
+            if visited[state / 64] & (1 << (state % 64)) != 0 {
+                return true;
+            }
+            visited[state / 64] |= 1 << (state % 64);
+            dir = self.turn.apply(dir);
         }
     }
-    panic!("No starting point found");
 }
 
-fn part2(map: &Vec<Vec<char>>, path: &Vec<(i32, i32, usize)>) -> u32 {
+// Positions on the guard's path where an extra obstacle traps it in a loop
+fn loop_obstacles(
+    map: &[Vec<char>],
+    path: &[(i32, i32, usize)],
+    rules: &PatrolRules,
+) -> Vec<(i32, i32)> {
     let mut counts: FxHashMap<(i32, i32), Vec<usize>> = Default::default();
     for (id, (x, y, _)) in path.iter().enumerate() {
         counts.entry((*x, *y)).or_default().push(id);
@@ -92,6 +520,7 @@ fn part2(map: &Vec<Vec<char>>, path: &Vec<(i32, i32, usize)>) -> u32 {
         .map(|(p, c)| (p, c[0]))
         .collect();
 
+    let jumps = JumpTable::new(map, rules);
     path.par_iter()
         .enumerate()
         .filter(|(id, (x, y, _dir))| {
@@ -104,16 +533,35 @@ fn part2(map: &Vec<Vec<char>>, path: &Vec<(i32, i32, usize)>) -> u32 {
                 }
             }
             let (start_x, start_y, start_dir) = path[id - 1];
-            walk_map(map, (start_x, start_y), start_dir, Some((*x, *y))).is_none()
+            jumps.loops((start_x, start_y), start_dir, (*x, *y))
         })
-        .count() as u32
+        .map(|(_, &(x, y, _))| (x, y))
+        .collect()
 }
 
-pub fn solve(input: &str) -> io::Result<(u32, u32)> {
-    let map = parse_input(input)?;
-    let (x, y, dir) = starting_point(&map.clone());
-    let mut path = walk_map(&map, (x, y), dir, None).unwrap();
-    let obstacles = part2(&map, &path);
+fn part2(map: &[Vec<char>], path: &[(i32, i32, usize)], rules: &PatrolRules) -> u32 {
+    loop_obstacles(map, path, rules).len() as u32
+}
+
+/// Every loop-causing obstacle with the loop it creates, walked from the
+/// guard's real starting point and ordered by position.
+pub fn loop_reports(map: &[Vec<char>], rules: &PatrolRules) -> Result<Vec<LoopReport>, MapError> {
+    validate_map(map, rules)?;
+    let (x, y, dir) = starting_point(map)?;
+    let path = walk_map(map, (x, y), dir, None, rules).ok_or(MapError::GuardNeverLeaves)?;
+    let mut obstacles = loop_obstacles(map, &path, rules);
+    obstacles.sort_by_key(|&(x, y)| (y, x));
+    Ok(obstacles
+        .into_par_iter()
+        .filter_map(|obstacle| find_loop(map, (x, y), dir, obstacle, rules))
+        .collect())
+}
+
+fn solve_map(map: Vec<Vec<char>>, rules: &PatrolRules) -> io::Result<(u32, u32)> {
+    validate_map(&map, rules)?;
+    let (x, y, dir) = starting_point(&map)?;
+    let mut path = walk_map(&map, (x, y), dir, None, rules).ok_or(MapError::GuardNeverLeaves)?;
+    let obstacles = part2(&map, &path, rules);
 
     path.sort();
     path.dedup_by(|a, b| a.0 == b.0 && a.1 == b.1);
@@ -121,9 +569,84 @@ pub fn solve(input: &str) -> io::Result<(u32, u32)> {
     Ok((path.len() as u32, obstacles))
 }
 
+pub fn solve(input: &str) -> io::Result<(u32, u32)> {
+    solve_map(parse_input(input)?, &PatrolRules::default())
+}
+
+/// Prints the solve result, or with `--loops` every obstacle position that traps
+/// the guard together with the loop it closes. `--patrol` moves all guards on
+/// the map at once. All modes follow `--turn left|right` and `--obstacles CHARS`;
+/// `--wrap` only applies to `--patrol`, as a wrapping guard never leaves the map.
+pub fn run(input: &str, args: &[String]) -> io::Result<()> {
+    let invalid = |message: String| io::Error::new(io::ErrorKind::InvalidInput, message);
+    let mut loops = false;
+    let mut patrolling = false;
+    let mut rules = PatrolRules::default();
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--loops" => loops = true,
+            "--patrol" => patrolling = true,
+            "--wrap" => rules.edges = Edges::Wrap,
+            "--turn" => {
+                rules.turn = match args.next().map(String::as_str) {
+                    Some("right") => Turn::Right,
+                    Some("left") => Turn::Left,
+                    _ => return Err(invalid("--turn needs left or right".to_string())),
+                }
+            }
+            "--obstacles" => {
+                let chars = args
+                    .next()
+                    .ok_or_else(|| invalid("--obstacles needs a value".to_string()))?;
+                rules.obstacles = chars.chars().collect();
+            }
+            _ => return Err(invalid(format!("Unknown option: {}", arg))),
+        }
+    }
+
+    if rules.edges == Edges::Wrap && (loops || !patrolling) {
+        return Err(invalid(
+            "--wrap is only supported with --patrol".to_string(),
+        ));
+    }
+
+    let map = parse_input(input)?;
+    if patrolling {
+        validate_map(&map, &rules)?;
+        if starting_points(&map).is_empty() {
+            return Err(MapError::NoGuard.into());
+        }
+        println!("Patrol: {:?}", patrol(&map, &rules));
+    }
+    if loops {
+        let reports = loop_reports(&map, &rules)?;
+        for report in reports.iter() {
+            let (x, y, dir) = report.cycle_start;
+            println!(
+                "obstacle at {:?}: enters a {}-step cycle ({} cells) at step {} on ({}, {}) facing {:?}, closes at step {}",
+                report.obstacle,
+                report.cycle_len,
+                report.cycle_cells.len(),
+                report.cycle_start_step,
+                x,
+                y,
+                GUARDS[dir],
+                report.closing_step
+            );
+        }
+        println!("{} loop-causing obstacle positions", reports.len());
+    }
+    if !patrolling && !loops {
+        println!("Day 06 result: {:?}", solve_map(map, &rules)?);
+    }
+    Ok(())
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
+    use std::time::Instant;
 
     #[test]
     fn test_walk_map() {
@@ -139,10 +662,409 @@ mod tests {
             vec!['#', '.', '.', '.', '.', '.', '.', '.', '.', '.'],
             vec!['.', '.', '.', '.', '.', '.', '#', '.', '.', '.'],
         ];
-        let start = starting_point(&map);
-        let path = walk_map(&map, (start.0, start.1), start.2, None).unwrap();
-        let obstacles = part2(&map, &path);
+        let start = starting_point(&map).unwrap();
+        let path = walk_map(
+            &map,
+            (start.0, start.1),
+            start.2,
+            None,
+            &PatrolRules::default(),
+        )
+        .unwrap();
+        let obstacles = part2(&map, &path, &PatrolRules::default());
 
         assert_eq!(obstacles, 6);
     }
+
+    fn grid(rows: &[&str]) -> Vec<Vec<char>> {
+        rows.iter().map(|row| row.chars().collect()).collect()
+    }
+
+    fn distinct_cells(path: &[(i32, i32, usize)]) -> usize {
+        path.iter()
+            .map(|&(x, y, _)| (x, y))
+            .collect::<FxHashSet<_>>()
+            .len()
+    }
+
+    // Tries an obstacle on every free cell, the slow way
+    fn brute_force_loops(map: &[Vec<char>], rules: &PatrolRules) -> u32 {
+        let (x, y, dir) = starting_point(map).unwrap();
+        let mut count = 0;
+        for (oy, row) in map.iter().enumerate() {
+            for (ox, &ch) in row.iter().enumerate() {
+                let obstacle = (ox as i32, oy as i32);
+                if ch == '.' && walk_map(map, (x, y), dir, Some(obstacle), rules).is_none() {
+                    count += 1;
+                }
+            }
+        }
+        count
+    }
+
+    #[test]
+    fn test_walk_wide_map() {
+        let map = grid(&["....#.....", ">........#", "........#."]);
+        let (x, y, dir) = starting_point(&map).unwrap();
+        let path = walk_map(&map, (x, y), dir, None, &PatrolRules::default()).unwrap();
+        assert_eq!(distinct_cells(&path), 9);
+    }
+
+    #[test]
+    fn test_walk_tall_map() {
+        let map = grid(&[
+            ".v.", "...", "...", "...", "...", "...", "...", "...", ".#.", "...",
+        ]);
+        let (x, y, dir) = starting_point(&map).unwrap();
+        let path = walk_map(&map, (x, y), dir, None, &PatrolRules::default()).unwrap();
+        assert_eq!(distinct_cells(&path), 9);
+        assert_eq!(path.last(), Some(&(0, 7, 2)));
+    }
+
+    #[test]
+    fn test_loops_on_rectangular_maps() {
+        let wide = grid(&[
+            "....#.........",
+            ".........#....",
+            "..............",
+            "..#...........",
+            ".......#......",
+            "..............",
+            ".#..^.........",
+            "........#.....",
+            "#.............",
+            "......#.......",
+        ]);
+        let tall = grid(&[
+            "....#.....",
+            ".........#",
+            "..........",
+            "..#.......",
+            ".......#..",
+            "..........",
+            ".#..^.....",
+            "........#.",
+            "#.........",
+            "......#...",
+            "..........",
+            "...#......",
+            "..........",
+        ]);
+        for map in [wide, tall] {
+            let (x, y, dir) = starting_point(&map).unwrap();
+            let path = walk_map(&map, (x, y), dir, None, &PatrolRules::default()).unwrap();
+            assert_eq!(
+                part2(&map, &path, &PatrolRules::default()),
+                brute_force_loops(&map, &PatrolRules::default())
+            );
+        }
+    }
+
+    #[test]
+    fn test_ragged_map_is_rejected() {
+        let err = parse_map("....\n..^.\n...\n".as_bytes()).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+        assert_eq!(
+            err.into_inner().unwrap().downcast::<MapError>().unwrap(),
+            Box::new(MapError::RaggedLine {
+                line: 3,
+                len: 3,
+                expected: 4
+            })
+        );
+        assert!(parse_map("....\n..^.\n....\n".as_bytes()).is_ok());
+    }
+
+    #[test]
+    fn test_loop_reports() {
+        let map = grid(&[
+            "....#.....",
+            ".........#",
+            "..........",
+            "..#.......",
+            ".......#..",
+            "..........",
+            ".#..^.....",
+            "........#.",
+            "#.........",
+            "......#...",
+        ]);
+        let reports = loop_reports(&map, &PatrolRules::default()).unwrap();
+        let obstacles = reports.iter().map(|r| r.obstacle).collect::<Vec<_>>();
+        assert_eq!(obstacles, [(3, 6), (6, 7), (7, 7), (1, 8), (3, 8), (7, 9)]);
+
+        for report in reports.iter() {
+            assert_eq!(
+                report.closing_step,
+                report.cycle_start_step + report.cycle_len
+            );
+            let (x, y, _) = report.cycle_start;
+            assert_eq!(report.cycle_cells.first(), Some(&(x, y)));
+        }
+
+        // First option from the puzzle: the guard is turned back up right
+        // after leaving its starting cell and circles the top-right block
+        let first = &reports[0];
+        assert_eq!(first.cycle_start, (4, 5, 3));
+        assert_eq!(first.cycle_start_step, 1);
+        assert_eq!(first.cycle_len, 18);
+        assert_eq!(first.cycle_cells.len(), 18);
+        assert!(first.cycle_cells.contains(&(8, 6)));
+    }
+
+    #[test]
+    fn test_left_turning_guard() {
+        let map = grid(&[".#...", "....#", ".....", "#^...", "...#."]);
+        let rules = PatrolRules {
+            turn: Turn::Left,
+            ..PatrolRules::default()
+        };
+        // Up to the wall, then left along the second row and off the map
+        let (path, cycle) = walk(&map, (1, 3), 3, None, &rules);
+        assert_eq!(cycle, None);
+        assert_eq!(path.last(), Some(&(0, 1, 2)));
+
+        // Counter-clockwise around a small block
+        let map = grid(&["..#.", "#...", "..^#", ".#.."]);
+        let (path, cycle) = walk(&map, (2, 2), 3, None, &rules);
+        assert_eq!(cycle, Some(1));
+        assert_eq!(distinct_cells(&path), 4);
+    }
+
+    #[test]
+    fn test_loops_follow_patrol_rules() {
+        // The puzzle example mirrored, with a second kind of obstacle: a guard
+        // turning left retraces the mirror image of the original patrol
+        let map = grid(&[
+            ".....O....",
+            "#.........",
+            "..........",
+            ".......#..",
+            "..O.......",
+            "..........",
+            ".....^..#.",
+            ".#........",
+            ".........#",
+            "...#......",
+        ]);
+        let rules = PatrolRules {
+            turn: Turn::Left,
+            obstacles: vec!['#', 'O'],
+            ..PatrolRules::default()
+        };
+        let (x, y, dir) = starting_point(&map).unwrap();
+        let path = walk_map(&map, (x, y), dir, None, &rules).unwrap();
+        assert_eq!(distinct_cells(&path), 41);
+        assert_eq!(part2(&map, &path, &rules), 6);
+        assert_eq!(brute_force_loops(&map, &rules), 6);
+
+        let reports = loop_reports(&map, &rules).unwrap();
+        let obstacles = reports.iter().map(|r| r.obstacle).collect::<Vec<_>>();
+        assert_eq!(obstacles, [(6, 6), (2, 7), (3, 7), (6, 8), (8, 8), (2, 9)]);
+    }
+
+    #[test]
+    fn test_wrap_needs_patrol() {
+        let args = |args: &[&str]| args.iter().map(|a| a.to_string()).collect::<Vec<_>>();
+        for options in [args(&["--wrap"]), args(&["--wrap", "--loops"])] {
+            let err = run("inputs/day06.txt", &options).unwrap_err();
+            assert_eq!(err.kind(), io::ErrorKind::InvalidInput);
+        }
+    }
+
+    #[test]
+    fn test_multiple_obstacle_chars() {
+        let map = grid(&["..O..", ".....", "..^..", "....."]);
+        let (path, _) = walk(&map, (2, 2), 3, None, &PatrolRules::default());
+        assert_eq!(distinct_cells(&path), 3);
+
+        let rules = PatrolRules {
+            obstacles: vec!['#', 'O'],
+            ..PatrolRules::default()
+        };
+        let (path, _) = walk(&map, (2, 2), 3, None, &rules);
+        assert_eq!(path.last(), Some(&(4, 1, 0)));
+    }
+
+    #[test]
+    fn test_wrap_around_edges() {
+        let map = grid(&[".....", "..^..", "....."]);
+        let rules = PatrolRules {
+            edges: Edges::Wrap,
+            ..PatrolRules::default()
+        };
+        assert_eq!(
+            patrol(&map, &rules),
+            PatrolOutcome::Loop {
+                start_tick: 0,
+                period: 3
+            }
+        );
+        let (path, cycle) = walk(&map, (2, 1), 3, None, &rules);
+        assert_eq!(cycle, Some(0));
+        assert_eq!(distinct_cells(&path), 3);
+    }
+
+    #[test]
+    fn test_boxed_in_guard_spins() {
+        let map = grid(&[".#.", "#^#", ".#."]);
+        let report = find_loop(&map, (1, 1), 3, (0, 0), &PatrolRules::default()).unwrap();
+        assert_eq!(report.cycle_len, 1);
+        assert_eq!(report.cycle_cells, [(1, 1)]);
+    }
+
+    #[test]
+    fn test_patrol_with_multiple_guards() {
+        let rules = PatrolRules::default();
+
+        // Both walk off the map without meeting
+        let map = grid(&["^....", "....v", "....."]);
+        assert_eq!(patrol(&map, &rules), PatrolOutcome::Exited { ticks: 2 });
+
+        // Head-on on the same row, meeting in the middle cell
+        let map = grid(&[">...<"]);
+        assert_eq!(
+            patrol(&map, &rules),
+            PatrolOutcome::Collision {
+                tick: 2,
+                position: (2, 0),
+                guards: (0, 1)
+            }
+        );
+
+        // Adjacent and facing each other, they swap cells on the first tick
+        let map = grid(&[".><."]);
+        assert_eq!(
+            patrol(&map, &rules),
+            PatrolOutcome::Collision {
+                tick: 1,
+                position: (2, 0),
+                guards: (0, 1)
+            }
+        );
+
+        // Two guards circling separate blocks forever
+        let map = grid(&[".#...#..", "...#...#", "#^..#^..", "..#...#."]);
+        let outcome = patrol(&map, &rules);
+        assert!(
+            matches!(outcome, PatrolOutcome::Loop { .. }),
+            "{:?}",
+            outcome
+        );
+    }
+
+    #[test]
+    fn test_guard_discovery_errors() {
+        assert_eq!(
+            starting_point(&grid(&["...", "..."])),
+            Err(MapError::NoGuard)
+        );
+        assert_eq!(
+            starting_point(&grid(&["^..", "..<"])),
+            Err(MapError::MultipleGuards(vec![(0, 0), (2, 1)]))
+        );
+        assert_eq!(starting_point(&grid(&["...", ".>."])), Ok((1, 1, 0)));
+        assert_eq!(
+            loop_reports(&grid(&["...", "..."]), &PatrolRules::default()).unwrap_err(),
+            MapError::NoGuard
+        );
+    }
+
+    #[test]
+    fn test_guard_that_never_leaves() {
+        let map = grid(&[".#..", "...#", "#^..", "..#."]);
+        let rules = PatrolRules::default();
+        assert_eq!(
+            loop_reports(&map, &rules).unwrap_err(),
+            MapError::GuardNeverLeaves
+        );
+        let err = solve_map(map, &rules).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+        assert_eq!(err.to_string(), "The guard never leaves the map");
+    }
+
+    #[test]
+    fn test_unknown_characters() {
+        let map = grid(&["..#", ".O.", "^.."]);
+        assert_eq!(
+            validate_map(&map, &PatrolRules::default()),
+            Err(MapError::UnknownChar {
+                ch: 'O',
+                x: 1,
+                y: 1
+            })
+        );
+        let rules = PatrolRules {
+            obstacles: vec!['#', 'O'],
+            ..PatrolRules::default()
+        };
+        assert_eq!(validate_map(&map, &rules), Ok(()));
+        assert_eq!(
+            loop_reports(&map, &PatrolRules::default()).unwrap_err(),
+            MapError::UnknownChar {
+                ch: 'O',
+                x: 1,
+                y: 1
+            }
+        );
+    }
+
+    #[test]
+    fn test_jump_table_matches_walk() {
+        let map = parse_input("inputs/day06.txt").unwrap();
+        let (x, y, dir) = starting_point(&map).unwrap();
+        let path = walk_map(&map, (x, y), dir, None, &PatrolRules::default()).unwrap();
+        let jumps = JumpTable::new(&map, &PatrolRules::default());
+
+        for window in path.windows(2).step_by(7) {
+            let (start_x, start_y, start_dir) = window[0];
+            let obstacle = (window[1].0, window[1].1);
+            if obstacle == (x, y) {
+                continue;
+            }
+            assert_eq!(
+                jumps.loops((start_x, start_y), start_dir, obstacle),
+                walk_map(
+                    &map,
+                    (start_x, start_y),
+                    start_dir,
+                    Some(obstacle),
+                    &PatrolRules::default()
+                )
+                .is_none(),
+                "obstacle at {:?}",
+                obstacle
+            );
+        }
+    }
+
+    // cargo test --release bench_loop_obstacles -- --ignored --nocapture
+    #[test]
+    #[ignore]
+    fn bench_loop_obstacles() {
+        let map = parse_input("inputs/day06.txt").unwrap();
+        let (x, y, dir) = starting_point(&map).unwrap();
+        let path = walk_map(&map, (x, y), dir, None, &PatrolRules::default()).unwrap();
+
+        // One cell-by-cell walk per candidate, as before the jump table
+        let start = Instant::now();
+        let mut candidates: Vec<(i32, i32)> = path[1..].iter().map(|&(x, y, _)| (x, y)).collect();
+        candidates.sort();
+        candidates.dedup();
+        let stepped = candidates
+            .par_iter()
+            .filter(|&&obstacle| {
+                walk_map(&map, (x, y), dir, Some(obstacle), &PatrolRules::default()).is_none()
+            })
+            .count();
+        let stepped_time = start.elapsed();
+
+        let start = Instant::now();
+        let jumped = loop_obstacles(&map, &path, &PatrolRules::default()).len();
+        let jumped_time = start.elapsed();
+
+        println!("stepped: {} obstacles in {:?}", stepped, stepped_time);
+        println!("jumped:  {} obstacles in {:?}", jumped, jumped_time);
+        assert_eq!(stepped, jumped);
+    }
 }
@@ -1,29 +1,304 @@
 use itertools::Itertools;
 use rayon::prelude::*;
+use rustc_hash::FxHashMap;
+use std::fmt;
 use std::fs;
 use std::io;
+use std::str::FromStr;
 
-#[derive(Clone, Copy, Debug)]
-enum Operator {
-    Add,
-    Mul,
-    Concat,
+/// Calibration results and intermediate values. All arithmetic is checked, so an
+/// expression that does not fit simply fails to match; the `u128` feature widens
+/// it for generated inputs with long number lists.
+#[cfg(not(feature = "u128"))]
+pub type Value = u64;
+#[cfg(feature = "u128")]
+pub type Value = u128;
+
+/// What undoing an operator from its right operand says about the left operand.
+pub enum Inverse {
+    /// No left operand produces the result.
+    Impossible,
+    /// Exactly this left operand produces the result.
+    Unique(Value),
+    /// Several (or unknown) left operands might, so the search has to evaluate
+    /// the preceding numbers forwards.
+    Ambiguous,
 }
 
-fn parse_input(input: &str) -> io::Result<Vec<(u64, Vec<u32>)>> {
-    fs::read_to_string(input)?
+/// A binary operator placed between calibration numbers. How consecutive
+/// operators combine is decided by the chosen `Evaluation`.
+pub trait Operator: Sync {
+    /// Combines the running value with the next number, `None` if undefined.
+    fn apply(&self, lhs: Value, rhs: Value) -> Option<Value>;
+
+    /// Solves `apply(lhs, rhs) == result` for `lhs`, letting the search work
+    /// backwards from the target and prune early.
+    fn inverse(&self, _result: Value, _rhs: Value) -> Inverse {
+        Inverse::Ambiguous
+    }
+
+    /// Binding strength under `Evaluation::Precedence`; higher binds tighter.
+    fn precedence(&self) -> u8 {
+        0
+    }
+
+    /// Whether `apply` never returns less than either operand when both are
+    /// non-zero, which lets the parenthesized search drop values above the target.
+    fn monotonic(&self) -> bool {
+        false
+    }
+
+    fn symbol(&self) -> &'static str;
+}
+
+/// How the operators of a calibration are combined.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Evaluation {
+    /// Strictly left-to-right, as the puzzle specifies.
+    LeftToRight,
+    /// Higher `Operator::precedence` binds tighter, ties associate to the left.
+    Precedence,
+    /// Any parenthesization of the expression. The search grows with every
+    /// distinct intermediate value, so long equations take a while.
+    Parenthesized,
+}
+
+impl FromStr for Evaluation {
+    type Err = io::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "ltr" => Ok(Evaluation::LeftToRight),
+            "precedence" => Ok(Evaluation::Precedence),
+            "parens" => Ok(Evaluation::Parenthesized),
+            _ => Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!("Unknown evaluation mode: {}", s),
+            )),
+        }
+    }
+}
+
+pub struct Add;
+pub struct Mul;
+pub struct Concat;
+pub struct Sub;
+pub struct Div;
+pub struct Pow;
+pub struct Xor;
+
+impl Operator for Add {
+    fn apply(&self, lhs: Value, rhs: Value) -> Option<Value> {
+        lhs.checked_add(rhs)
+    }
+
+    fn inverse(&self, result: Value, rhs: Value) -> Inverse {
+        result
+            .checked_sub(rhs)
+            .map_or(Inverse::Impossible, Inverse::Unique)
+    }
+
+    fn precedence(&self) -> u8 {
+        1
+    }
+
+    fn monotonic(&self) -> bool {
+        true
+    }
+
+    fn symbol(&self) -> &'static str {
+        "+"
+    }
+}
+
+impl Operator for Mul {
+    fn apply(&self, lhs: Value, rhs: Value) -> Option<Value> {
+        lhs.checked_mul(rhs)
+    }
+
+    fn inverse(&self, result: Value, rhs: Value) -> Inverse {
+        match (result, rhs) {
+            // Multiplying by zero hides whatever came before
+            (0, 0) => Inverse::Ambiguous,
+            (_, 0) => Inverse::Impossible,
+            _ if result.is_multiple_of(rhs) => Inverse::Unique(result / rhs),
+            _ => Inverse::Impossible,
+        }
+    }
+
+    fn precedence(&self) -> u8 {
+        2
+    }
+
+    fn monotonic(&self) -> bool {
+        true
+    }
+
+    fn symbol(&self) -> &'static str {
+        "*"
+    }
+}
+
+impl Operator for Concat {
+    fn apply(&self, lhs: Value, rhs: Value) -> Option<Value> {
+        lhs.checked_mul(digit_shift(rhs)?)?.checked_add(rhs)
+    }
+
+    fn inverse(&self, result: Value, rhs: Value) -> Inverse {
+        match digit_shift(rhs) {
+            Some(pow) if result % pow == rhs => Inverse::Unique(result / pow),
+            _ => Inverse::Impossible,
+        }
+    }
+
+    // Joining digits binds tighter than any arithmetic
+    fn precedence(&self) -> u8 {
+        4
+    }
+
+    fn monotonic(&self) -> bool {
+        true
+    }
+
+    fn symbol(&self) -> &'static str {
+        "||"
+    }
+}
+
+impl Operator for Sub {
+    // Running values are unsigned, so going below zero is not a valid evaluation
+    fn apply(&self, lhs: Value, rhs: Value) -> Option<Value> {
+        lhs.checked_sub(rhs)
+    }
+
+    fn inverse(&self, result: Value, rhs: Value) -> Inverse {
+        result
+            .checked_add(rhs)
+            .map_or(Inverse::Impossible, Inverse::Unique)
+    }
+
+    fn precedence(&self) -> u8 {
+        1
+    }
+
+    fn symbol(&self) -> &'static str {
+        "-"
+    }
+}
+
+impl Operator for Div {
+    // Integer division truncates, so it has no unique inverse
+    fn apply(&self, lhs: Value, rhs: Value) -> Option<Value> {
+        lhs.checked_div(rhs)
+    }
+
+    fn precedence(&self) -> u8 {
+        2
+    }
+
+    fn symbol(&self) -> &'static str {
+        "/"
+    }
+}
+
+impl Operator for Pow {
+    fn apply(&self, lhs: Value, rhs: Value) -> Option<Value> {
+        lhs.checked_pow(u32::try_from(rhs).ok()?)
+    }
+
+    fn precedence(&self) -> u8 {
+        3
+    }
+
+    fn symbol(&self) -> &'static str {
+        "**"
+    }
+}
+
+impl Operator for Xor {
+    fn apply(&self, lhs: Value, rhs: Value) -> Option<Value> {
+        Some(lhs ^ rhs)
+    }
+
+    fn inverse(&self, result: Value, rhs: Value) -> Inverse {
+        Inverse::Unique(result ^ rhs)
+    }
+
+    fn symbol(&self) -> &'static str {
+        "^"
+    }
+}
+
+const PART1_OPS: [&dyn Operator; 2] = [&Add, &Mul];
+const PART2_OPS: [&dyn Operator; 3] = [&Add, &Mul, &Concat];
+const ALL_OPS: [&dyn Operator; 7] = [&Add, &Mul, &Concat, &Sub, &Div, &Pow, &Xor];
+
+/// Parses a comma separated list of operator symbols, e.g. `+,*,||`.
+fn parse_operators(spec: &str) -> io::Result<Vec<&'static dyn Operator>> {
+    spec.split(',')
+        .map(|symbol| {
+            ALL_OPS
+                .iter()
+                .find(|op| op.symbol() == symbol.trim())
+                .copied()
+                .ok_or_else(|| {
+                    io::Error::new(
+                        io::ErrorKind::InvalidInput,
+                        format!("Unknown operator: {}", symbol),
+                    )
+                })
+        })
+        .collect()
+}
+
+/// A calibration together with one operator sequence that satisfies it.
+pub struct Equation<'a> {
+    result: Value,
+    numbers: &'a [u32],
+    ops: Vec<&'a dyn Operator>,
+    /// Parenthesized groups as inclusive ranges of number indices.
+    groups: Vec<(usize, usize)>,
+}
+
+impl fmt::Display for Equation<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{} =", self.result)?;
+        for (i, number) in self.numbers.iter().enumerate() {
+            if i > 0 {
+                write!(f, " {}", self.ops[i - 1].symbol())?;
+            }
+            write!(f, " ")?;
+            for _ in self.groups.iter().filter(|(first, _)| *first == i) {
+                write!(f, "(")?;
+            }
+            write!(f, "{}", number)?;
+            for _ in self.groups.iter().filter(|(_, last)| *last == i) {
+                write!(f, ")")?;
+            }
+        }
+        Ok(())
+    }
+}
+
+fn parse_input(input: &str) -> io::Result<Vec<(Value, Vec<u32>)>> {
+    parse_calibrations(&fs::read_to_string(input)?)
+}
+
+fn parse_calibrations(content: &str) -> io::Result<Vec<(Value, Vec<u32>)>> {
+    content
         .lines()
         .map(|line| {
-            let mut parts = line.split(':');
-            let result_number = parts
-                .next()
-                .unwrap()
+            let (result, numbers) = line.split_once(':').ok_or_else(|| {
+                io::Error::new(
+                    io::ErrorKind::InvalidData,
+                    format!("Missing ':' in calibration {:?}", line),
+                )
+            })?;
+            let result_number = result
                 .trim()
-                .parse::<u64>()
+                .parse::<Value>()
                 .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
-            let numbers = parts
-                .next()
-                .unwrap()
+            let numbers = numbers
                 .split_whitespace()
                 .map(|n| {
                     n.parse::<u32>()
@@ -35,58 +310,518 @@ fn parse_input(input: &str) -> io::Result<Vec<(u64, Vec<u32>)>> {
         .collect()
 }
 
-fn check_equation(result: u64, numbers: &Vec<u32>, ops: &Vec<Operator>) -> bool {
-    let op_num = numbers.len() - 1;
-    std::iter::repeat(ops)
-        .take(op_num)
-        .multi_cartesian_product()
-        .par_bridge()
-        .any(|perm| {
-            let mut current = numbers[0] as u64;
-            for (i, &op) in perm.iter().enumerate() {
-                match op {
-                    Operator::Add => current += numbers[i + 1] as u64,
-                    Operator::Mul => current *= numbers[i + 1] as u64,
-                    Operator::Concat => {
-                        current = format!("{}{}", current, numbers[i + 1])
-                            .parse::<u64>()
-                            .unwrap()
+fn digits(mut n: Value) -> u32 {
+    let mut count = 1;
+    while n >= 10 {
+        n /= 10;
+        count += 1;
+    }
+    count
+}
+
+// The power of ten that moves a number left by the digits of `n`, if it fits.
+fn digit_shift(n: Value) -> Option<Value> {
+    (10 as Value).checked_pow(digits(n))
+}
+
+// Same backward search as `operator_sequences`, but only answers whether a
+// sequence exists, so nothing is allocated unless an inverse is ambiguous.
+fn check_equation(result: Value, numbers: &[u32], ops: &[&dyn Operator]) -> bool {
+    let (&last, rest) = match numbers.split_last() {
+        Some(split) => split,
+        None => return false,
+    };
+    let last = last as Value;
+    if rest.is_empty() {
+        return result == last;
+    }
+
+    ops.iter().any(|op| match op.inverse(result, last) {
+        Inverse::Impossible => false,
+        Inverse::Unique(lhs) => check_equation(lhs, rest, ops),
+        Inverse::Ambiguous => prefix_values(rest, ops)
+            .into_keys()
+            .any(|value| op.apply(value, last) == Some(result)),
+    })
+}
+
+// At most `limit` of the operator sequences that satisfy the calibration.
+fn find_solutions<'a>(
+    result: Value,
+    numbers: &'a [u32],
+    ops: &[&'a dyn Operator],
+    limit: usize,
+) -> Vec<Equation<'a>> {
+    operator_sequences(result, numbers, ops, limit)
+        .into_iter()
+        .map(|ops| Equation {
+            result,
+            numbers,
+            ops,
+            groups: Vec::new(),
+        })
+        .collect()
+}
+
+// Every value `numbers` evaluate to left-to-right, with how many operator
+// sequences produce it. Distinct values are kept once, however many sequences
+// reach them.
+fn prefix_values(numbers: &[u32], ops: &[&dyn Operator]) -> FxHashMap<Value, u64> {
+    let mut values = FxHashMap::default();
+    let Some((&first, rest)) = numbers.split_first() else {
+        return values;
+    };
+    values.insert(first as Value, 1);
+    for &number in rest {
+        let mut next: FxHashMap<Value, u64> = FxHashMap::default();
+        for (&value, &count) in values.iter() {
+            for op in ops {
+                if let Some(value) = op.apply(value, number as Value) {
+                    let entry = next.entry(value).or_default();
+                    *entry = entry.saturating_add(count);
+                }
+            }
+        }
+        values = next;
+    }
+    values
+}
+
+// The values the prefix must reach for `op` with `last` to give `result`.
+// Without a unique inverse, they are read off the reachable prefix values.
+fn operand_targets(
+    result: Value,
+    last: Value,
+    rest: &[u32],
+    op: &dyn Operator,
+    ops: &[&dyn Operator],
+) -> Vec<Value> {
+    match op.inverse(result, last) {
+        Inverse::Impossible => Vec::new(),
+        Inverse::Unique(lhs) => vec![lhs],
+        Inverse::Ambiguous => {
+            let mut targets: Vec<Value> = prefix_values(rest, ops)
+                .into_keys()
+                .filter(|&value| op.apply(value, last) == Some(result))
+                .collect();
+            targets.sort_unstable();
+            targets
+        }
+    }
+}
+
+// Works backwards from the target: the last operator must be undone by the last
+// number, which cuts most branches before they are ever expanded. Operators
+// without a unique inverse continue from each distinct prefix value that works.
+// Stops after `limit` sequences.
+fn operator_sequences<'a>(
+    result: Value,
+    numbers: &[u32],
+    ops: &[&'a dyn Operator],
+    limit: usize,
+) -> Vec<Vec<&'a dyn Operator>> {
+    let (&last, rest) = match numbers.split_last() {
+        Some(split) => split,
+        None => return Vec::new(),
+    };
+    let last = last as Value;
+    if rest.is_empty() {
+        return if result == last && limit > 0 {
+            vec![Vec::new()]
+        } else {
+            Vec::new()
+        };
+    }
+
+    let mut sequences = Vec::new();
+    for &op in ops {
+        for lhs in operand_targets(result, last, rest, op, ops) {
+            if sequences.len() >= limit {
+                return sequences;
+            }
+            let prefixes = operator_sequences(lhs, rest, ops, limit - sequences.len());
+            sequences.extend(prefixes.into_iter().map(|mut prefix| {
+                prefix.push(op);
+                prefix
+            }));
+        }
+    }
+    sequences
+}
+
+// How many operator sequences satisfy the calibration, without listing them.
+fn count_sequences(result: Value, numbers: &[u32], ops: &[&dyn Operator]) -> u64 {
+    let (&last, rest) = match numbers.split_last() {
+        Some(split) => split,
+        None => return 0,
+    };
+    let last = last as Value;
+    if rest.is_empty() {
+        return (result == last) as u64;
+    }
+
+    let mut count: u64 = 0;
+    for &op in ops {
+        let ways = match op.inverse(result, last) {
+            Inverse::Impossible => 0,
+            Inverse::Unique(lhs) => count_sequences(lhs, rest, ops),
+            Inverse::Ambiguous => prefix_values(rest, ops)
+                .into_iter()
+                .filter(|&(value, _)| op.apply(value, last) == Some(result))
+                .fold(0u64, |acc, (_, ways)| acc.saturating_add(ways)),
+        };
+        count = count.saturating_add(ways);
+    }
+    count
+}
+
+// Exhaustive forward evaluation, keeping the sequences whose value is accepted.
+// Operators wait on a stack until one of lower or equal precedence arrives; with
+// precedence ignored every operator is applied as soon as the next one is chosen.
+fn forward_sequences<'a>(
+    numbers: &[u32],
+    ops: &[&'a dyn Operator],
+    by_precedence: bool,
+    first_only: bool,
+    accept: &dyn Fn(Value) -> bool,
+) -> Vec<Vec<&'a dyn Operator>> {
+    struct Search<'s, 'a> {
+        ops: &'s [&'a dyn Operator],
+        by_precedence: bool,
+        first_only: bool,
+        accept: &'s dyn Fn(Value) -> bool,
+        current: Vec<&'a dyn Operator>,
+        sequences: Vec<Vec<&'a dyn Operator>>,
+    }
+
+    impl<'a> Search<'_, 'a> {
+        fn level(&self, op: &dyn Operator) -> u8 {
+            if self.by_precedence {
+                op.precedence()
+            } else {
+                0
+            }
+        }
+
+        fn walk(&mut self, value: Value, pending: &[(Value, &'a dyn Operator)], numbers: &[u32]) {
+            let Some((&next, rest)) = numbers.split_first() else {
+                let total = pending
+                    .iter()
+                    .rev()
+                    .try_fold(value, |rhs, (lhs, op)| op.apply(*lhs, rhs));
+                if total.is_some_and(|total| (self.accept)(total)) {
+                    self.sequences.push(self.current.clone());
+                }
+                return;
+            };
+            for &op in self.ops {
+                if self.first_only && !self.sequences.is_empty() {
+                    return;
+                }
+                let mut pending = pending.to_vec();
+                let mut value = Some(value);
+                while let Some(&(lhs, top)) = pending.last() {
+                    if self.level(top) < self.level(op) {
+                        break;
+                    }
+                    value = value.and_then(|rhs| top.apply(lhs, rhs));
+                    pending.pop();
+                }
+                if let Some(value) = value {
+                    pending.push((value, op));
+                    self.current.push(op);
+                    self.walk(next as Value, &pending, rest);
+                    self.current.pop();
+                }
+            }
+        }
+    }
+
+    let mut search = Search {
+        ops,
+        by_precedence,
+        first_only,
+        accept,
+        current: Vec::new(),
+        sequences: Vec::new(),
+    };
+    if let Some((&first, rest)) = numbers.split_first() {
+        search.walk(first as Value, &[], rest);
+    }
+    search.sequences
+}
+
+// How a value of `numbers[first..=last]` was built: the number of expressions
+// producing it and, for compound ones, the split point, operator index and the
+// values of both halves of one of them.
+struct Reachable {
+    count: u64,
+    split: Option<(usize, usize, Value, Value)>,
+}
+
+// Interval DP over every parenthesization: `table[first][last]` maps each value
+// of that sub-expression to how it can be reached.
+fn parenthesized_values(
+    result: Value,
+    numbers: &[u32],
+    ops: &[&dyn Operator],
+) -> Vec<Vec<FxHashMap<Value, Reachable>>> {
+    let n = numbers.len();
+    let bound = (ops.iter().all(|op| op.monotonic()) && !numbers.contains(&0)).then_some(result);
+    let mut table: Vec<Vec<FxHashMap<Value, Reachable>>> = (0..n)
+        .map(|_| (0..n).map(|_| FxHashMap::default()).collect())
+        .collect();
+
+    for (i, &number) in numbers.iter().enumerate() {
+        table[i][i].insert(
+            number as Value,
+            Reachable {
+                count: 1,
+                split: None,
+            },
+        );
+    }
+    for len in 2..=n {
+        for first in 0..=n - len {
+            let last = first + len - 1;
+            let mut values: FxHashMap<Value, Reachable> = FxHashMap::default();
+            for split in first..last {
+                for (&lhs, left) in table[first][split].iter() {
+                    for (&rhs, right) in table[split + 1][last].iter() {
+                        for (op_index, op) in ops.iter().enumerate() {
+                            let Some(value) = op.apply(lhs, rhs) else {
+                                continue;
+                            };
+                            if bound.is_some_and(|bound| value > bound) {
+                                continue;
+                            }
+                            let count = left.count.saturating_mul(right.count);
+                            let reachable = values.entry(value).or_insert(Reachable {
+                                count: 0,
+                                split: Some((split, op_index, lhs, rhs)),
+                            });
+                            reachable.count = reachable.count.saturating_add(count);
+                        }
                     }
                 }
             }
-            current == result
+            table[first][last] = values;
+        }
+    }
+    table
+}
+
+// One parenthesization reaching `result` and the number of distinct ones.
+fn find_grouping<'a>(
+    result: Value,
+    numbers: &'a [u32],
+    ops: &[&'a dyn Operator],
+) -> Option<(Equation<'a>, u64)> {
+    fn rebuild<'a>(
+        table: &[Vec<FxHashMap<Value, Reachable>>],
+        ops: &[&'a dyn Operator],
+        (first, last, value): (usize, usize, Value),
+        chosen: &mut [Option<&'a dyn Operator>],
+        groups: &mut Vec<(usize, usize)>,
+    ) {
+        if let Some((split, op_index, lhs, rhs)) = table[first][last][&value].split {
+            chosen[split] = Some(ops[op_index]);
+            for (first, last, value) in [(first, split, lhs), (split + 1, last, rhs)] {
+                if first < last {
+                    groups.push((first, last));
+                }
+                rebuild(table, ops, (first, last, value), chosen, groups);
+            }
+        }
+    }
+
+    if numbers.is_empty() {
+        return None;
+    }
+    let table = parenthesized_values(result, numbers, ops);
+    let count = table[0][numbers.len() - 1].get(&result)?.count;
+    let mut chosen = vec![None; numbers.len() - 1];
+    let mut groups = Vec::new();
+    rebuild(
+        &table,
+        ops,
+        (0, numbers.len() - 1, result),
+        &mut chosen,
+        &mut groups,
+    );
+    let equation = Equation {
+        result,
+        numbers,
+        ops: chosen.into_iter().flatten().collect(),
+        groups,
+    };
+    Some((equation, count))
+}
+
+fn satisfies(result: Value, numbers: &[u32], ops: &[&dyn Operator], eval: Evaluation) -> bool {
+    match eval {
+        Evaluation::LeftToRight => check_equation(result, numbers, ops),
+        Evaluation::Precedence => {
+            !forward_sequences(numbers, ops, true, true, &|value| value == result).is_empty()
+        }
+        Evaluation::Parenthesized => match numbers.len() {
+            0 => false,
+            n => parenthesized_values(result, numbers, ops)[0][n - 1].contains_key(&result),
+        },
+    }
+}
+
+// One satisfying expression and how many exist under the chosen evaluation.
+fn find_witness<'a>(
+    result: Value,
+    numbers: &'a [u32],
+    ops: &[&'a dyn Operator],
+    eval: Evaluation,
+) -> Option<(Equation<'a>, u64)> {
+    let solutions: Vec<Equation> = match eval {
+        Evaluation::LeftToRight => {
+            let witness = find_solutions(result, numbers, ops, 1).into_iter().next()?;
+            return Some((witness, count_sequences(result, numbers, ops)));
+        }
+        Evaluation::Precedence => {
+            forward_sequences(numbers, ops, true, false, &|value| value == result)
+                .into_iter()
+                .map(|ops| Equation {
+                    result,
+                    numbers,
+                    ops,
+                    groups: Vec::new(),
+                })
+                .collect()
+        }
+        Evaluation::Parenthesized => return find_grouping(result, numbers, ops),
+    };
+    let count = solutions.len() as u64;
+    solutions.into_iter().next().map(|witness| (witness, count))
+}
+
+fn calibration_total(
+    calibrations: &[(Value, Vec<u32>)],
+    ops: &[&dyn Operator],
+    eval: Evaluation,
+) -> io::Result<Value> {
+    calibrations
+        .par_iter()
+        .filter(|(result, numbers)| satisfies(*result, numbers, ops, eval))
+        .map(|(result, _)| Some(*result))
+        .try_reduce(|| 0, |acc, result| acc.checked_add(result))
+        .ok_or_else(|| {
+            io::Error::new(
+                io::ErrorKind::InvalidData,
+                "Calibration total does not fit the value type",
+            )
         })
 }
 
-pub fn solve(input: &str) -> io::Result<(u64, u64)> {
-    let part1_ops = vec![Operator::Add, Operator::Mul];
-    let part2_ops = vec![Operator::Add, Operator::Mul, Operator::Concat];
+pub fn solve(input: &str) -> io::Result<(Value, Value)> {
     let calibrations = parse_input(input)?;
-    let (part1, part2): (u64, u64) = calibrations
-        .par_iter()
-        .map(|(result, numbers)| {
-            let mut p1 = 0;
-            let mut p2 = 0;
-            if check_equation(*result, numbers, &part1_ops) {
-                p1 = *result;
+    let part1 = calibration_total(&calibrations, &PART1_OPS, Evaluation::LeftToRight)?;
+    let part2 = calibration_total(&calibrations, &PART2_OPS, Evaluation::LeftToRight)?;
+
+    Ok((part1, part2))
+}
+
+/// Prints the solve result, or the total for the operators chosen with
+/// `--ops +,*,-` under the `--eval ltr|precedence|parens` semantics. With
+/// `--explain` every calibration is listed alongside a witness expression and
+/// the number of expressions that satisfy it.
+pub fn run(input: &str, args: &[String]) -> io::Result<()> {
+    let mut explain = false;
+    let mut ops = None;
+    let mut eval = None;
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--explain" => explain = true,
+            "--ops" | "--eval" => {
+                let value = args.next().ok_or_else(|| {
+                    io::Error::new(
+                        io::ErrorKind::InvalidInput,
+                        format!("{} needs a value", arg),
+                    )
+                })?;
+                if arg == "--ops" {
+                    ops = Some(parse_operators(value)?);
+                } else {
+                    eval = Some(value.parse::<Evaluation>()?);
+                }
             }
-            if check_equation(*result, numbers, &part2_ops) {
-                p2 = *result;
+            _ => {
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidInput,
+                    format!("Unknown option: {}", arg),
+                ))
             }
-            (p1, p2)
-        })
-        .reduce(|| (0, 0), |(acc1, acc2), (p1, p2)| (acc1 + p1, acc2 + p2));
+        }
+    }
 
-    Ok((part1, part2))
+    let calibrations = parse_input(input)?;
+    if ops.is_none() && eval.is_none() && !explain {
+        println!("Day 07 result: {:?}", solve(input)?);
+        return Ok(());
+    }
+    let ops = ops.unwrap_or_else(|| PART2_OPS.to_vec());
+    let eval = eval.unwrap_or(Evaluation::LeftToRight);
+
+    if explain {
+        let mut passing = 0;
+        for (result, numbers) in calibrations.iter() {
+            match find_witness(*result, numbers, &ops, eval) {
+                Some((witness, count)) => {
+                    passing += 1;
+                    println!("{}  ({} solution(s))", witness, count);
+                }
+                None => println!("{}: {:?} has no solution", result, numbers),
+            }
+        }
+        println!("{} of {} calibrations pass", passing, calibrations.len());
+    }
+
+    let symbols = ops.iter().map(|op| op.symbol()).join(" ");
+    println!(
+        "Calibration total with {} ({:?}): {}",
+        symbols,
+        eval,
+        calibration_total(&calibrations, &ops, eval)?
+    );
+
+    Ok(())
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use std::time::Instant;
+
+    // Forward enumeration of every operator combination, kept as a reference
+    // for the benchmark.
+    fn check_equation_exhaustive(result: Value, numbers: &[u32], ops: &[&dyn Operator]) -> bool {
+        std::iter::repeat_n(ops, numbers.len() - 1)
+            .multi_cartesian_product()
+            .par_bridge()
+            .any(|perm| {
+                let mut current = Some(numbers[0] as Value);
+                for (i, op) in perm.iter().enumerate() {
+                    current = current.and_then(|value| op.apply(value, numbers[i + 1] as Value));
+                }
+                current == Some(result)
+            })
+    }
+
+    fn printed(solutions: &[Equation]) -> Vec<String> {
+        let mut printed = solutions.iter().map(|s| s.to_string()).collect::<Vec<_>>();
+        printed.sort();
+        printed
+    }
 
     #[test]
     fn test_check_equation() {
-        let ops = vec![Operator::Add, Operator::Mul, Operator::Concat];
+        let ops: Vec<&dyn Operator> = vec![&Add, &Mul, &Concat];
         assert_eq!(check_equation(190, &vec![10, 19], &ops), true);
         assert_eq!(check_equation(3267, &vec![81, 40, 27], &ops), true);
         assert_eq!(check_equation(83, &vec![17, 5], &ops), false);
@@ -97,4 +832,304 @@ mod tests {
         assert_eq!(check_equation(21037, &vec![9, 7, 18, 13], &ops), false);
         assert_eq!(check_equation(292, &vec![11, 6, 16, 20], &ops), true);
     }
+
+    #[test]
+    fn test_check_equation_edge_cases() {
+        let ops = PART2_OPS;
+        assert!(check_equation(7, &[7], &ops));
+        assert!(!check_equation(7, &[], &ops));
+        assert!(check_equation(0, &[5, 3, 0], &ops)); // 5 + 3 * 0
+        assert!(check_equation(530, &[5, 3, 0], &ops)); // 5 || 3 || 0
+        assert!(!check_equation(12, &[1, 2], &PART1_OPS));
+    }
+
+    #[test]
+    fn test_matches_exhaustive_search() {
+        let calibrations = parse_input("inputs/day07.txt").unwrap();
+        let ops = PART2_OPS;
+        for (result, numbers) in calibrations.iter().filter(|(_, n)| n.len() <= 6) {
+            assert_eq!(
+                check_equation(*result, numbers, &ops),
+                check_equation_exhaustive(*result, numbers, &ops),
+                "{}: {:?}",
+                result,
+                numbers
+            );
+        }
+    }
+
+    // cargo test --release bench_check_equation -- --ignored --nocapture
+    #[test]
+    #[ignore]
+    fn bench_check_equation() {
+        let calibrations = parse_input("inputs/day07.txt").unwrap();
+        let ops = PART2_OPS;
+
+        let start = Instant::now();
+        let exhaustive = calibrations
+            .iter()
+            .filter(|(result, numbers)| check_equation_exhaustive(*result, numbers, &ops))
+            .count();
+        let exhaustive_time = start.elapsed();
+
+        let start = Instant::now();
+        let pruned = calibrations
+            .iter()
+            .filter(|(result, numbers)| check_equation(*result, numbers, &ops))
+            .count();
+        let pruned_time = start.elapsed();
+
+        println!(
+            "exhaustive: {} matches in {:?}",
+            exhaustive, exhaustive_time
+        );
+        println!("pruned:     {} matches in {:?}", pruned, pruned_time);
+        assert_eq!(exhaustive, pruned);
+    }
+
+    #[test]
+    fn test_find_solutions() {
+        let ops = PART2_OPS;
+
+        let solutions = find_solutions(292, &[11, 6, 16, 20], &ops, usize::MAX);
+        assert_eq!(printed(&solutions), ["292 = 11 + 6 * 16 + 20"]);
+
+        let solutions = find_solutions(156, &[15, 6], &ops, usize::MAX);
+        assert_eq!(printed(&solutions), ["156 = 15 || 6"]);
+
+        let solutions = find_solutions(3267, &[81, 40, 27], &ops, usize::MAX);
+        assert_eq!(
+            printed(&solutions),
+            ["3267 = 81 * 40 + 27", "3267 = 81 + 40 * 27"]
+        );
+
+        assert!(find_solutions(83, &[17, 5], &ops, usize::MAX).is_empty());
+    }
+
+    #[test]
+    fn test_find_solutions_multiply_by_zero() {
+        let solutions = find_solutions(0, &[2, 3, 0], &PART1_OPS, usize::MAX);
+        assert_eq!(printed(&solutions), ["0 = 2 * 3 * 0", "0 = 2 + 3 * 0"]);
+    }
+
+    #[test]
+    fn test_find_solutions_agrees_with_check() {
+        let calibrations = parse_input("inputs/day07.txt").unwrap();
+        let ops = PART2_OPS;
+        for (result, numbers) in calibrations.iter() {
+            let solutions = find_solutions(*result, numbers, &ops, usize::MAX);
+            assert_eq!(
+                !solutions.is_empty(),
+                check_equation(*result, numbers, &ops)
+            );
+        }
+    }
+
+    #[test]
+    fn test_custom_operators() {
+        let ops = parse_operators("+,-,/,**,^").unwrap();
+        assert_eq!(
+            printed(&find_solutions(2, &[10, 3, 5], &ops, usize::MAX)),
+            ["2 = 10 + 3 / 5", "2 = 10 - 3 - 5", "2 = 10 - 3 ^ 5"]
+        );
+        assert_eq!(
+            printed(&find_solutions(64, &[2, 3, 2], &ops, usize::MAX)),
+            ["64 = 2 ** 3 ** 2"]
+        );
+        assert_eq!(
+            printed(&find_solutions(6, &[5, 3], &ops, usize::MAX)),
+            ["6 = 5 ^ 3"]
+        );
+        // Subtraction below zero is not a valid evaluation
+        assert!(!check_equation(1, &[2, 5, 4], &ops[1..2]));
+    }
+
+    #[test]
+    fn test_ambiguous_inverse_matches_exhaustive_search() {
+        let ops = parse_operators("+,*,/,**,^").unwrap();
+        let cases = [
+            (7, vec![15, 2, 1]),
+            (9, vec![20, 6, 2, 1]),
+            (81, vec![3, 2, 2]),
+        ];
+        for (result, numbers) in cases.iter() {
+            assert!(check_equation(*result, numbers, &ops));
+            assert!(check_equation_exhaustive(*result, numbers, &ops));
+        }
+        assert!(!check_equation(1000, &[3, 2, 2], &ops));
+    }
+
+    #[test]
+    fn test_precedence_evaluation() {
+        let ops = PART1_OPS;
+        // 11 + 6 * 16 + 20 = 127 with precedence, 292 left-to-right
+        assert!(satisfies(
+            127,
+            &[11, 6, 16, 20],
+            &ops,
+            Evaluation::Precedence
+        ));
+        assert!(!satisfies(
+            292,
+            &[11, 6, 16, 20],
+            &ops,
+            Evaluation::Precedence
+        ));
+        assert!(satisfies(
+            292,
+            &[11, 6, 16, 20],
+            &ops,
+            Evaluation::LeftToRight
+        ));
+
+        // 2 || 3 * 4 binds the concatenation first: 23 * 4
+        assert!(satisfies(
+            92,
+            &[2, 3, 4],
+            &PART2_OPS,
+            Evaluation::Precedence
+        ));
+        assert!(!satisfies(
+            212,
+            &[2, 3, 4],
+            &PART2_OPS,
+            Evaluation::Precedence
+        ));
+
+        let (witness, count) =
+            find_witness(127, &[11, 6, 16, 20], &ops, Evaluation::Precedence).unwrap();
+        assert_eq!(witness.to_string(), "127 = 11 + 6 * 16 + 20");
+        assert_eq!(count, 1);
+    }
+
+    #[test]
+    fn test_custom_precedence() {
+        struct Shl;
+        impl Operator for Shl {
+            fn apply(&self, lhs: Value, rhs: Value) -> Option<Value> {
+                lhs.checked_shl(u32::try_from(rhs).ok()?)
+            }
+
+            fn precedence(&self) -> u8 {
+                5
+            }
+
+            fn symbol(&self) -> &'static str {
+                "<<"
+            }
+        }
+
+        let ops: [&dyn Operator; 2] = [&Add, &Shl];
+        // 1 + (2 << 3) rather than (1 + 2) << 3
+        assert!(satisfies(17, &[1, 2, 3], &ops, Evaluation::Precedence));
+        assert!(!satisfies(24, &[1, 2, 3], &ops, Evaluation::Precedence));
+        assert!(satisfies(24, &[1, 2, 3], &ops, Evaluation::LeftToRight));
+    }
+
+    #[test]
+    fn test_parenthesized_evaluation() {
+        let ops = PART1_OPS;
+        // 3 * (4 + 5) needs brackets under both other semantics
+        assert!(satisfies(27, &[3, 4, 5], &ops, Evaluation::Parenthesized));
+        assert!(!satisfies(27, &[3, 4, 5], &ops, Evaluation::LeftToRight));
+        assert!(!satisfies(27, &[3, 4, 5], &ops, Evaluation::Precedence));
+
+        let (witness, count) =
+            find_witness(27, &[3, 4, 5], &ops, Evaluation::Parenthesized).unwrap();
+        assert_eq!(witness.to_string(), "27 = 3 * (4 + 5)");
+        assert_eq!(count, 1);
+
+        // (1 + 2) + 3, 1 + (2 + 3), (1 * 2) * 3 and 1 * (2 * 3)
+        let (_, count) = find_witness(6, &[1, 2, 3], &ops, Evaluation::Parenthesized).unwrap();
+        assert_eq!(count, 4);
+    }
+
+    #[test]
+    fn test_parenthesized_covers_left_to_right() {
+        let calibrations = parse_input("inputs/day07.txt").unwrap();
+        for (result, numbers) in calibrations.iter().filter(|(_, n)| n.len() <= 6) {
+            if check_equation(*result, numbers, &PART2_OPS) {
+                assert!(satisfies(
+                    *result,
+                    numbers,
+                    &PART2_OPS,
+                    Evaluation::Parenthesized
+                ));
+            }
+        }
+    }
+
+    #[test]
+    fn test_overflow_is_not_a_match() {
+        let big = [u32::MAX; 8];
+        for eval in [
+            Evaluation::LeftToRight,
+            Evaluation::Precedence,
+            Evaluation::Parenthesized,
+        ] {
+            assert!(!satisfies(Value::MAX, &big, &PART2_OPS, eval));
+            // Only has to finish without panicking, whatever the answer
+            satisfies(7, &big, &ALL_OPS, eval);
+        }
+        assert!(find_solutions(Value::MAX, &big, &PART2_OPS, usize::MAX).is_empty());
+
+        // Wrapping multiplication would land on 0 here
+        let ops = [&Mul as &dyn Operator];
+        let halves = [1 << 16, 1 << 16, 1 << 16, 1 << 16];
+        assert!(!check_equation(0, &halves, &ops));
+        assert!(!satisfies(0, &halves, &ops, Evaluation::Precedence));
+
+        assert_eq!(Concat.apply(1, Value::MAX), None);
+        assert!(matches!(Concat.inverse(5, Value::MAX), Inverse::Impossible));
+    }
+
+    #[test]
+    fn test_calibration_total_overflow() {
+        let ops = [&Mul as &dyn Operator];
+        let fits = vec![(12, vec![3, 4]), (35, vec![5, 7])];
+        assert_eq!(
+            calibration_total(&fits, &ops, Evaluation::LeftToRight).unwrap(),
+            47
+        );
+
+        // Each product just fits, but two of them do not
+        let terms = (Value::BITS / 32) as usize;
+        let largest = (u32::MAX as Value).pow(terms as u32);
+        let calibrations = vec![(largest, vec![u32::MAX; terms]); 2];
+        assert!(calibration_total(&calibrations, &ops, Evaluation::LeftToRight).is_err());
+    }
+
+    #[test]
+    fn test_parse_operators() {
+        let ops = parse_operators("+, *,||").unwrap();
+        assert_eq!(ops.iter().map(|op| op.symbol()).join(" "), "+ * ||");
+        assert!(parse_operators("+,%").is_err());
+    }
+
+    #[test]
+    fn test_ambiguous_inverse_is_not_enumerated() {
+        // Every one of the 2^29 prefixes times 0 gives 0; only the distinct
+        // prefix values are ever looked at
+        let mut numbers = vec![1; 30];
+        numbers.push(0);
+        let (witness, count) =
+            find_witness(0, &numbers, &PART1_OPS, Evaluation::LeftToRight).unwrap();
+        assert!(witness.to_string().ends_with("* 0"));
+        assert_eq!(count, 1 << 29);
+        assert!(check_equation(0, &numbers, &PART1_OPS));
+        assert_eq!(find_solutions(0, &numbers, &PART1_OPS, 3).len(), 3);
+    }
+
+    #[test]
+    fn test_parse_errors() {
+        assert_eq!(
+            parse_calibrations("190: 10 19\n3267: 81 40 27").unwrap(),
+            [(190, vec![10, 19]), (3267, vec![81, 40, 27])]
+        );
+        let err = parse_calibrations("190: 10 19\n3267 81 40 27").unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+        assert!(err.to_string().contains("Missing ':'"));
+        assert!(parse_calibrations("x: 1").is_err());
+        assert!(parse_calibrations("1: 2 y").is_err());
+    }
 }
@@ -1,101 +1,349 @@
-use std::collections::{HashMap, HashSet};
+use std::collections::{BTreeMap, HashMap, HashSet};
 use std::fs;
 use std::io;
+use std::ops::Range;
 
+/// A point on a grid of `D` dimensions: `[x, y]` for maps, `[x, y, z]` for
+/// layered maps.
 #[derive(Hash, Eq, PartialEq, Debug, Clone)]
-struct Pos(usize, usize);
+struct Pos<const D: usize = 2>([isize; D]);
 
-fn parse_map(file_path: &str) -> Vec<Vec<char>> {
-    let content = fs::read_to_string(file_path).expect("Failed to read file");
-    content
+impl<const D: usize> Pos<D> {
+    // `self + delta * num / den`, if every coordinate lands on the grid. A zero
+    // `den` places nothing.
+    fn offset(&self, delta: &[isize; D], num: isize, den: isize) -> Option<Self> {
+        let mut coords = self.0;
+        for (coord, d) in coords.iter_mut().zip(delta) {
+            let scaled = d.checked_mul(num)?;
+            if scaled.checked_rem(den)? != 0 {
+                return None;
+            }
+            *coord = coord.checked_add(scaled.checked_div(den)?)?;
+        }
+        Some(Pos(coords))
+    }
+
+    // Vector from `self` to `other`, if it is representable
+    fn delta(&self, other: &Self) -> Option<[isize; D]> {
+        let mut delta = [0; D];
+        for (d, (a, b)) in delta.iter_mut().zip(self.0.iter().zip(other.0.iter())) {
+            *d = b.checked_sub(*a)?;
+        }
+        Some(delta)
+    }
+}
+
+/// The region antinodes may fall in, as half-open coordinate ranges.
+#[derive(Clone, Debug)]
+struct Bounds<const D: usize = 2>([Range<isize>; D]);
+
+impl Bounds {
+    fn grid(width: usize, height: usize) -> Self {
+        Bounds([0..width as isize, 0..height as isize])
+    }
+}
+
+impl<const D: usize> Bounds<D> {
+    fn contains(&self, pos: &Pos<D>) -> bool {
+        self.0
+            .iter()
+            .zip(pos.0.iter())
+            .all(|(range, c)| range.contains(c))
+    }
+}
+
+/// Which cells on the line through two antennas count as harmonic antinodes.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Harmonics {
+    /// Multiples of the full distance between the antennas, as the puzzle does.
+    Stepped,
+    /// Every grid cell on the line, stepping by the distance reduced by its gcd.
+    Exact,
+}
+
+/// Where antinodes sit relative to a pair of antennas `a` and `b`, as fractions
+/// `t = num / den` of the way from `a` to `b`. Only points that land exactly on
+/// the grid count.
+#[derive(Clone, Debug, PartialEq)]
+pub struct AntinodeRule {
+    pub fractions: Vec<(isize, isize)>,
+}
+
+impl AntinodeRule {
+    /// Outside the pair, where one antenna is `k` times as far as the other;
+    /// `k = 2` is the puzzle's rule. `k` must be greater than 1.
+    pub fn times(k: isize) -> Self {
+        AntinodeRule {
+            fractions: vec![(-1, k - 1), (k, k - 1)],
+        }
+    }
+
+    pub fn midpoint() -> Self {
+        AntinodeRule {
+            fractions: vec![(1, 2)],
+        }
+    }
+
+    pub fn thirds() -> Self {
+        AntinodeRule {
+            fractions: vec![(1, 3), (2, 3)],
+        }
+    }
+
+    /// Parses `mid`, `thirds`, a distance factor like `3`, or explicit
+    /// fractions like `-1,1/2,2`.
+    fn parse(spec: &str) -> io::Result<Self> {
+        let invalid = || {
+            io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!("Invalid antinode ratio: {}", spec),
+            )
+        };
+        match spec {
+            "mid" => return Ok(Self::midpoint()),
+            "thirds" => return Ok(Self::thirds()),
+            _ => {}
+        }
+        if let Ok(k) = spec.parse::<isize>() {
+            return if k > 1 {
+                Ok(Self::times(k))
+            } else {
+                Err(invalid())
+            };
+        }
+        let fractions = spec
+            .split(',')
+            .map(|fraction| {
+                let (num, den) = fraction.split_once('/').unwrap_or((fraction, "1"));
+                let num = num.trim().parse::<isize>().map_err(|_| invalid())?;
+                let den = den.trim().parse::<isize>().map_err(|_| invalid())?;
+                if den == 0 {
+                    return Err(invalid());
+                }
+                Ok((num, den))
+            })
+            .collect::<io::Result<Vec<_>>>()?;
+        Ok(AntinodeRule { fractions })
+    }
+}
+
+/// Which antinodes a frequency produces.
+#[derive(Clone, Debug, PartialEq)]
+pub enum Resonance {
+    /// One spacing of the pair before its first antenna and one after it.
+    Pairs,
+    /// Antinodes all along the line through every pair.
+    Harmonic(Harmonics),
+    /// Antinodes placed by a custom rule.
+    Ratio(AntinodeRule),
+}
+
+/// Antinode breakdown for a single antenna frequency.
+#[derive(Debug, PartialEq)]
+pub struct FrequencyReport {
+    pub frequency: char,
+    pub antennas: usize,
+    pub antinodes: usize,
+    /// Antinodes that some other frequency produces as well.
+    pub shared: usize,
+}
+
+fn parse_map(file_path: &str) -> io::Result<Vec<Vec<char>>> {
+    let content = fs::read_to_string(file_path)?;
+    Ok(content
         .lines()
         .map(|line| line.chars().collect::<Vec<char>>())
-        .collect::<Vec<Vec<char>>>()
+        .collect::<Vec<Vec<char>>>())
+}
+
+// Layers of a 3D field are ordinary maps separated by blank lines, the first one
+// at z = 0.
+fn parse_layers(content: &str) -> Vec<Vec<Vec<char>>> {
+    let mut layers = vec![];
+    let mut layer: Vec<Vec<char>> = vec![];
+    for line in content.lines() {
+        if line.trim().is_empty() {
+            if !layer.is_empty() {
+                layers.push(std::mem::take(&mut layer));
+            }
+        } else {
+            layer.push(line.chars().collect());
+        }
+    }
+    if !layer.is_empty() {
+        layers.push(layer);
+    }
+    layers
+}
+
+// Cells from `start` onwards in steps of `delta` (negated if `sign` is -1)
+// while they stay in bounds
+fn ray<'a, const D: usize>(
+    start: &Pos<D>,
+    delta: [isize; D],
+    sign: isize,
+    bounds: &'a Bounds<D>,
+) -> impl Iterator<Item = Pos<D>> + 'a {
+    std::iter::successors(Some(start.clone()), move |pos| pos.offset(&delta, sign, 1))
+        .take_while(|pos| bounds.contains(pos))
 }
 
-fn calculate_antinodes(antennas: &[Pos], height: usize, width: usize) -> HashSet<Pos> {
+fn calculate_antinodes<const D: usize>(antennas: &[Pos<D>], bounds: &Bounds<D>) -> HashSet<Pos<D>> {
     let mut antinodes = HashSet::new();
     for (i, pos1) in antennas.iter().enumerate() {
         for pos2 in antennas.iter().skip(i + 1) {
-            let dx = pos2.0 as isize - pos1.0 as isize;
-            let dy = pos2.1 as isize - pos1.1 as isize;
+            let Some(delta) = pos1.delta(pos2) else {
+                continue;
+            };
 
-            for k in [-1, 1].iter() {
-                let new_pos = Pos(
-                    (pos1.0 as isize + k * dx) as usize,
-                    (pos1.1 as isize + k * dy) as usize,
-                );
+            let candidates = [pos1.offset(&delta, -1, 1), pos1.offset(&delta, 1, 1)];
+            antinodes.extend(
+                candidates
+                    .into_iter()
+                    .flatten()
+                    .filter(|pos| bounds.contains(pos)),
+            );
+        }
+    }
+    antinodes
+}
 
-                if new_pos.0 < width && new_pos.1 < height {
-                    antinodes.insert(new_pos);
-                }
-            }
+fn calculate_ratio_antinodes<const D: usize>(
+    antennas: &[Pos<D>],
+    bounds: &Bounds<D>,
+    rule: &AntinodeRule,
+) -> HashSet<Pos<D>> {
+    let mut antinodes = HashSet::new();
+    for (i, pos1) in antennas.iter().enumerate() {
+        for pos2 in antennas.iter().skip(i + 1) {
+            let Some(delta) = pos1.delta(pos2) else {
+                continue;
+            };
+
+            antinodes.extend(
+                rule.fractions
+                    .iter()
+                    .filter_map(|&(num, den)| pos1.offset(&delta, num, den))
+                    .filter(|pos| bounds.contains(pos)),
+            );
         }
     }
     antinodes
 }
 
-fn calculate_harmonical_antinodes(antennas: &[Pos], height: usize, width: usize) -> HashSet<Pos> {
-    let mut antinodes = antennas.iter().cloned().collect::<HashSet<Pos>>();
+// Harmonic lines only end at the bounds, so those should be finite.
+fn calculate_harmonical_antinodes<const D: usize>(
+    antennas: &[Pos<D>],
+    bounds: &Bounds<D>,
+) -> HashSet<Pos<D>> {
+    let mut antinodes = antennas.iter().cloned().collect::<HashSet<_>>();
 
     for (i, pos1) in antennas.iter().enumerate() {
         for pos2 in antennas.iter().skip(i + 1) {
-            let dx = pos2.0 as isize - pos1.0 as isize;
-            let dy = pos2.1 as isize - pos1.1 as isize;
-
-            let mut k = 1;
-            loop {
-                let new_pos1 = Pos(
-                    (pos1.0 as isize - k * dx) as usize,
-                    (pos1.1 as isize - k * dy) as usize,
-                );
-                let new_pos2 = Pos(
-                    (pos2.0 as isize + k * dx) as usize,
-                    (pos2.1 as isize + k * dy) as usize,
-                );
-
-                let mut added = false;
-                if new_pos1.0 < width && new_pos1.1 < height {
-                    antinodes.insert(new_pos1);
-                    added = true;
-                }
-                if new_pos2.0 < width && new_pos2.1 < height {
-                    antinodes.insert(new_pos2);
-                    added = true;
-                }
+            let Some(delta) = pos1.delta(pos2) else {
+                continue;
+            };
 
-                if !added {
-                    break;
-                }
-                k += 1;
-            }
+            antinodes.extend(ray(pos1, delta, -1, bounds));
+            antinodes.extend(ray(pos2, delta, 1, bounds));
         }
     }
     antinodes
 }
 
-fn calc(map: &Vec<Vec<char>>) -> (u64, u64) {
-    let mut positions: HashMap<char, Vec<Pos>> = HashMap::new();
-    let height = map.len();
-    let width = map[0].len();
+fn gcd(a: isize, b: isize) -> isize {
+    if b == 0 {
+        a.abs()
+    } else {
+        gcd(b, a % b)
+    }
+}
 
-    for (y, row) in map.iter().enumerate() {
-        for (x, &ch) in row.iter().enumerate() {
-            if ch.is_alphanumeric() {
-                positions.entry(ch).or_insert_with(Vec::new).push(Pos(x, y));
+fn calculate_collinear_antinodes<const D: usize>(
+    antennas: &[Pos<D>],
+    bounds: &Bounds<D>,
+) -> HashSet<Pos<D>> {
+    let mut antinodes = antennas.iter().cloned().collect::<HashSet<_>>();
+
+    for (i, pos1) in antennas.iter().enumerate() {
+        for pos2 in antennas.iter().skip(i + 1) {
+            let Some(delta) = pos1.delta(pos2) else {
+                continue;
+            };
+            let step = delta.iter().fold(0, |acc, &d| gcd(acc, d));
+            if step == 0 {
+                continue;
             }
+
+            let delta = delta.map(|d| d / step);
+            antinodes.extend(ray(pos1, delta, -1, bounds));
+            antinodes.extend(ray(pos1, delta, 1, bounds));
         }
     }
+    antinodes
+}
 
-    let unique_positions = positions
-        .values()
-        .flat_map(|pos_list| calculate_antinodes(pos_list, height, width))
-        .collect::<HashSet<_>>();
+fn group_by_frequency<const D: usize>(
+    cells: impl Iterator<Item = (char, Pos<D>)>,
+) -> BTreeMap<char, Vec<Pos<D>>> {
+    let mut positions: BTreeMap<char, Vec<Pos<D>>> = BTreeMap::new();
+    for (ch, pos) in cells {
+        if ch.is_alphanumeric() {
+            positions.entry(ch).or_default().push(pos);
+        }
+    }
+    positions
+}
 
-    let harmonical_positions = positions
-        .values()
-        .flat_map(|pos_list| calculate_harmonical_antinodes(pos_list, height, width))
-        .collect::<HashSet<_>>();
+fn antennas_by_frequency(map: &[Vec<char>]) -> BTreeMap<char, Vec<Pos>> {
+    group_by_frequency(map.iter().enumerate().flat_map(|(y, row)| {
+        row.iter()
+            .enumerate()
+            .map(move |(x, &ch)| (ch, Pos([x as isize, y as isize])))
+    }))
+}
+
+fn resonate<const D: usize>(
+    groups: BTreeMap<char, Vec<Pos<D>>>,
+    bounds: &Bounds<D>,
+    resonance: &Resonance,
+) -> BTreeMap<char, HashSet<Pos<D>>> {
+    groups
+        .into_iter()
+        .map(|(frequency, pos_list)| {
+            let antinodes = match resonance {
+                Resonance::Pairs => calculate_antinodes(&pos_list, bounds),
+                Resonance::Harmonic(Harmonics::Stepped) => {
+                    calculate_harmonical_antinodes(&pos_list, bounds)
+                }
+                Resonance::Harmonic(Harmonics::Exact) => {
+                    calculate_collinear_antinodes(&pos_list, bounds)
+                }
+                Resonance::Ratio(rule) => calculate_ratio_antinodes(&pos_list, bounds, rule),
+            };
+            (frequency, antinodes)
+        })
+        .collect()
+}
+
+fn antinodes_by_frequency(
+    map: &[Vec<char>],
+    resonance: &Resonance,
+) -> BTreeMap<char, HashSet<Pos>> {
+    let bounds = Bounds::grid(map.first().map_or(0, |row| row.len()), map.len());
+    resonate(antennas_by_frequency(map), &bounds, resonance)
+}
+
+fn all_antinodes(map: &[Vec<char>], resonance: &Resonance) -> HashSet<Pos> {
+    antinodes_by_frequency(map, resonance)
+        .into_values()
+        .flatten()
+        .collect()
+}
+
+fn calc(map: &[Vec<char>], harmonics: Harmonics) -> (u64, u64) {
+    let unique_positions = all_antinodes(map, &Resonance::Pairs);
+    let harmonical_positions = all_antinodes(map, &Resonance::Harmonic(harmonics));
 
     (
         unique_positions.len() as u64,
@@ -103,9 +351,511 @@ fn calc(map: &Vec<Vec<char>>) -> (u64, u64) {
     )
 }
 
+// Number of distinct antinodes in a layered 3D field
+fn calc_layers(layers: &[Vec<Vec<char>>], resonance: &Resonance) -> u64 {
+    let height = layers.iter().map(|layer| layer.len()).max().unwrap_or(0);
+    let width = layers
+        .iter()
+        .flatten()
+        .map(|row| row.len())
+        .max()
+        .unwrap_or(0);
+    let bounds = Bounds([
+        0..width as isize,
+        0..height as isize,
+        0..layers.len() as isize,
+    ]);
+
+    let cells = layers.iter().enumerate().flat_map(|(z, layer)| {
+        layer.iter().enumerate().flat_map(move |(y, row)| {
+            row.iter()
+                .enumerate()
+                .map(move |(x, &ch)| (ch, Pos([x as isize, y as isize, z as isize])))
+        })
+    });
+    resonate(group_by_frequency(cells), &bounds, resonance)
+        .into_values()
+        .flatten()
+        .collect::<HashSet<_>>()
+        .len() as u64
+}
+
+fn frequency_reports(map: &[Vec<char>], resonance: &Resonance) -> Vec<FrequencyReport> {
+    let antennas = antennas_by_frequency(map);
+    let antinodes = antinodes_by_frequency(map, resonance);
+
+    let mut producers: HashMap<&Pos, usize> = HashMap::new();
+    for pos in antinodes.values().flatten() {
+        *producers.entry(pos).or_default() += 1;
+    }
+
+    antinodes
+        .iter()
+        .map(|(&frequency, positions)| FrequencyReport {
+            frequency,
+            antennas: antennas[&frequency].len(),
+            antinodes: positions.len(),
+            shared: positions.iter().filter(|pos| producers[pos] > 1).count(),
+        })
+        .collect()
+}
+
+// Draws antinodes as `#` over free cells the way the puzzle illustrates them.
+// With colour, antinodes and the antennas that hide one are shown in red.
+fn render_overlay(map: &[Vec<char>], antinodes: &HashSet<Pos>, color: bool) -> String {
+    let mut out = String::new();
+    for (y, row) in map.iter().enumerate() {
+        for (x, &ch) in row.iter().enumerate() {
+            let is_antinode = antinodes.contains(&Pos([x as isize, y as isize]));
+            let shown = if is_antinode && ch == '.' { '#' } else { ch };
+            if color && is_antinode {
+                out.push_str(&format!("\x1b[1;31m{}\x1b[0m", shown));
+            } else {
+                out.push(shown);
+            }
+        }
+        out.push('\n');
+    }
+    out
+}
+
 pub fn solve(input: &str) -> io::Result<(u64, u64)> {
-    let map = parse_map(input);
-    let (part1, part2) = calc(&map);
+    let map = parse_map(input)?;
+    let (part1, part2) = calc(&map, Harmonics::Stepped);
 
     Ok((part1, part2))
 }
+
+/// Prints the solve result; `--exact` counts every grid cell on the antenna
+/// lines for the second part instead of whole multiples of the spacing, and is
+/// rejected in the other modes unless `--harmonic` is given.
+/// `--report` breaks the antinodes down per frequency and `--overlay` draws them
+/// on the map (`--color` to highlight), for the pair rule, with `--harmonic`
+/// for the second part's rule or with `--ratio` for a custom one (see
+/// `AntinodeRule::parse`), but not both. `--layered` reads the input as a 3D field of maps
+/// separated by blank lines.
+pub fn run(input: &str, args: &[String]) -> io::Result<()> {
+    let invalid = |message: String| io::Error::new(io::ErrorKind::InvalidInput, message);
+    let mut harmonics = Harmonics::Stepped;
+    let mut harmonic = false;
+    let mut rule = None;
+    let mut report = false;
+    let mut overlay = false;
+    let mut color = false;
+    let mut layered = false;
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--exact" => harmonics = Harmonics::Exact,
+            "--harmonic" => harmonic = true,
+            "--ratio" => {
+                let spec = args
+                    .next()
+                    .ok_or_else(|| invalid("--ratio needs a value".to_string()))?;
+                rule = Some(AntinodeRule::parse(spec)?);
+            }
+            "--report" => report = true,
+            "--overlay" => overlay = true,
+            "--color" => color = true,
+            "--layered" => layered = true,
+            _ => return Err(invalid(format!("Unknown option: {}", arg))),
+        }
+    }
+
+    let resonance = match (rule, harmonic) {
+        (Some(_), true) => {
+            return Err(invalid(
+                "--harmonic and --ratio are different rules, pick one".to_string(),
+            ))
+        }
+        (Some(rule), false) => Resonance::Ratio(rule),
+        (None, true) => Resonance::Harmonic(harmonics),
+        (None, false) => Resonance::Pairs,
+    };
+    // Outside the plain solve, `--exact` only changes the harmonic rule
+    let solving = resonance == Resonance::Pairs && !report && !overlay && !layered;
+    if harmonics == Harmonics::Exact && !solving && !matches!(resonance, Resonance::Harmonic(_)) {
+        return Err(invalid(
+            "--exact only applies to the harmonic rule, add --harmonic".to_string(),
+        ));
+    }
+    if layered {
+        let layers = parse_layers(&fs::read_to_string(input)?);
+        println!(
+            "{} layers, {:?}: {} antinodes",
+            layers.len(),
+            resonance,
+            calc_layers(&layers, &resonance)
+        );
+        return Ok(());
+    }
+
+    let map = parse_map(input)?;
+    if report {
+        println!("freq  antennas  antinodes  shared");
+        for entry in frequency_reports(&map, &resonance) {
+            println!(
+                "{:>4}  {:>8}  {:>9}  {:>6}",
+                entry.frequency, entry.antennas, entry.antinodes, entry.shared
+            );
+        }
+    }
+    if overlay {
+        print!(
+            "{}",
+            render_overlay(&map, &all_antinodes(&map, &resonance), color)
+        );
+    }
+    if let Resonance::Ratio(_) = resonance {
+        if !report && !overlay {
+            println!(
+                "{:?}: {} antinodes",
+                resonance,
+                all_antinodes(&map, &resonance).len()
+            );
+        }
+    } else if !report && !overlay {
+        println!(
+            "Day 08 result ({:?}): {:?}",
+            harmonics,
+            calc(&map, harmonics)
+        );
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn grid(rows: &[&str]) -> Vec<Vec<char>> {
+        rows.iter().map(|row| row.chars().collect()).collect()
+    }
+
+    fn example() -> Vec<Vec<char>> {
+        grid(&[
+            "............",
+            "........0...",
+            ".....0......",
+            ".......0....",
+            "....0.......",
+            "......A.....",
+            "............",
+            "............",
+            "........A...",
+            ".........A..",
+            "............",
+            "............",
+        ])
+    }
+
+    #[test]
+    fn test_antinodes_on_example() {
+        assert_eq!(calc(&example(), Harmonics::Stepped).0, 11);
+        // The puzzle's own rule puts the second antinode beyond the second antenna
+        let twice_as_far = Resonance::Ratio(AntinodeRule::times(2));
+        assert_eq!(all_antinodes(&example(), &twice_as_far).len(), 14);
+    }
+
+    #[test]
+    fn test_pair_antinodes() {
+        let bounds = Bounds::grid(10, 10);
+        let antinodes = calculate_antinodes(&[Pos([4, 3]), Pos([5, 5])], &bounds);
+        assert_eq!(antinodes, HashSet::from([Pos([3, 1]), Pos([5, 5])]));
+
+        // One side falls off the top edge
+        let antinodes = calculate_antinodes(&[Pos([4, 1]), Pos([5, 3])], &bounds);
+        assert_eq!(antinodes, HashSet::from([Pos([5, 3])]));
+    }
+
+    #[test]
+    fn test_single_antennas_are_harmonic_antinodes() {
+        let map = grid(&["a...", "....", "...b"]);
+        assert_eq!(calc(&map, Harmonics::Stepped), (0, 2));
+        assert_eq!(calc(&map, Harmonics::Exact), (0, 2));
+    }
+
+    #[test]
+    fn test_offset_bounds() {
+        let bounds = Bounds([-5..5, -5..5]);
+        let antennas = [Pos([-1, -1]), Pos([1, 1])];
+        assert_eq!(
+            calculate_antinodes(&antennas, &bounds),
+            HashSet::from([Pos([-3, -3]), Pos([1, 1])])
+        );
+        assert_eq!(
+            calculate_harmonical_antinodes(&antennas, &bounds),
+            HashSet::from([
+                Pos([-5, -5]),
+                Pos([-3, -3]),
+                Pos([-1, -1]),
+                Pos([1, 1]),
+                Pos([3, 3])
+            ])
+        );
+    }
+
+    #[test]
+    fn test_unbounded_grid_does_not_wrap() {
+        let bounds = Bounds([isize::MIN..isize::MAX, isize::MIN..isize::MAX]);
+        let antennas = [Pos([isize::MAX - 3, 0]), Pos([isize::MAX - 1, 0])];
+        // The far antinode would be past isize::MAX
+        assert_eq!(
+            calculate_ratio_antinodes(&antennas, &bounds, &AntinodeRule::times(2)),
+            HashSet::from([Pos([isize::MAX - 5, 0])])
+        );
+
+        // Antennas too far apart to even take their difference
+        let antennas = [Pos([isize::MIN, 0]), Pos([isize::MAX, 0])];
+        assert!(calculate_antinodes(&antennas, &bounds).is_empty());
+    }
+
+    #[test]
+    fn test_harmonics_on_example() {
+        assert_eq!(calc(&example(), Harmonics::Stepped).1, 34);
+        // No pair in the example is spaced by a multiple of a smaller step
+        assert_eq!(calc(&example(), Harmonics::Exact).1, 34);
+    }
+
+    #[test]
+    fn test_overlay_matches_puzzle() {
+        let map = example();
+        let twice_as_far = Resonance::Ratio(AntinodeRule::times(2));
+        let overlay = render_overlay(&map, &all_antinodes(&map, &twice_as_far), false);
+        assert_eq!(
+            overlay,
+            [
+                "......#....#",
+                "...#....0...",
+                "....#0....#.",
+                "..#....0....",
+                "....0....#..",
+                ".#....A.....",
+                "...#........",
+                "#......#....",
+                "........A...",
+                ".........A..",
+                "..........#.",
+                "..........#.",
+                "",
+            ]
+            .join("\n")
+        );
+
+        let harmonic = Resonance::Harmonic(Harmonics::Stepped);
+        let overlay = render_overlay(&map, &all_antinodes(&map, &harmonic), false);
+        assert_eq!(
+            overlay,
+            [
+                "##....#....#",
+                ".#.#....0...",
+                "..#.#0....#.",
+                "..##...0....",
+                "....0....#..",
+                ".#...#A....#",
+                "...#..#.....",
+                "#....#.#....",
+                "..#.....A...",
+                "....#....A..",
+                ".#........#.",
+                "...#......##",
+                "",
+            ]
+            .join("\n")
+        );
+    }
+
+    #[test]
+    fn test_colored_overlay() {
+        let map = grid(&["a.a..", "....."]);
+        let overlay = render_overlay(&map, &all_antinodes(&map, &Resonance::Pairs), true);
+        assert_eq!(overlay, "a.\x1b[1;31ma\x1b[0m..\n.....\n");
+    }
+
+    #[test]
+    fn test_frequency_reports() {
+        let reports = frequency_reports(&example(), &Resonance::Pairs);
+        assert_eq!(
+            reports,
+            [
+                FrequencyReport {
+                    frequency: '0',
+                    antennas: 4,
+                    antinodes: 7,
+                    shared: 1,
+                },
+                FrequencyReport {
+                    frequency: 'A',
+                    antennas: 3,
+                    antinodes: 5,
+                    shared: 1,
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn test_exact_harmonics_fill_gcd_gaps() {
+        let bounds = Bounds::grid(10, 10);
+        let antennas = [Pos([0, 0]), Pos([2, 4])];
+
+        let stepped = calculate_harmonical_antinodes(&antennas, &bounds);
+        let exact = calculate_collinear_antinodes(&antennas, &bounds);
+        assert_eq!(
+            stepped,
+            HashSet::from([Pos([0, 0]), Pos([2, 4]), Pos([4, 8])])
+        );
+        assert_eq!(
+            exact,
+            HashSet::from([
+                Pos([0, 0]),
+                Pos([1, 2]),
+                Pos([2, 4]),
+                Pos([3, 6]),
+                Pos([4, 8])
+            ])
+        );
+
+        // Coprime spacing leaves nothing in between, so both agree
+        let antennas = [Pos([1, 1]), Pos([3, 4])];
+        assert_eq!(
+            calculate_harmonical_antinodes(&antennas, &bounds),
+            calculate_collinear_antinodes(&antennas, &bounds)
+        );
+    }
+
+    #[test]
+    fn test_exact_harmonics_on_map() {
+        let map = grid(&["a.....", "......", "..a...", "......", "....a.", "......"]);
+        assert_eq!(calc(&map, Harmonics::Stepped).1, 3);
+        assert_eq!(calc(&map, Harmonics::Exact).1, 6);
+
+        // Exact only adds the cells between multiples of the spacing
+        for map in [map, example(), grid(&["a...", "....", "...b"])] {
+            let stepped = all_antinodes(&map, &Resonance::Harmonic(Harmonics::Stepped));
+            let exact = all_antinodes(&map, &Resonance::Harmonic(Harmonics::Exact));
+            assert!(exact.is_superset(&stepped));
+        }
+    }
+
+    #[test]
+    fn test_ratio_rules() {
+        let bounds = Bounds::grid(20, 20);
+        let antennas = [Pos([3, 3]), Pos([9, 9])];
+        let antinodes = |rule: AntinodeRule| calculate_ratio_antinodes(&antennas, &bounds, &rule);
+        assert_eq!(
+            antinodes(AntinodeRule::times(2)),
+            HashSet::from([Pos([15, 15])])
+        );
+        assert_eq!(
+            antinodes(AntinodeRule::times(3)),
+            HashSet::from([Pos([0, 0]), Pos([12, 12])])
+        );
+        assert_eq!(
+            antinodes(AntinodeRule::midpoint()),
+            HashSet::from([Pos([6, 6])])
+        );
+        assert_eq!(
+            antinodes(AntinodeRule::thirds()),
+            HashSet::from([Pos([5, 5]), Pos([7, 7])])
+        );
+
+        // Points between grid cells don't count
+        let antennas = [Pos([0, 0]), Pos([3, 1])];
+        assert!(
+            calculate_ratio_antinodes(&antennas, &bounds, &AntinodeRule::midpoint()).is_empty()
+        );
+    }
+
+    #[test]
+    fn test_pairs_as_ratio() {
+        let map = example();
+        assert_eq!(
+            all_antinodes(
+                &map,
+                &Resonance::Ratio(AntinodeRule::parse("-1,1").unwrap())
+            ),
+            all_antinodes(&map, &Resonance::Pairs)
+        );
+    }
+
+    #[test]
+    fn test_parse_ratio() {
+        assert_eq!(AntinodeRule::parse("2").unwrap(), AntinodeRule::times(2));
+        assert_eq!(
+            AntinodeRule::parse("mid").unwrap(),
+            AntinodeRule::midpoint()
+        );
+        assert_eq!(
+            AntinodeRule::parse("-1, 1/3,2").unwrap().fractions,
+            [(-1, 1), (1, 3), (2, 1)]
+        );
+        assert!(AntinodeRule::parse("1").is_err());
+        assert!(AntinodeRule::parse("0").is_err());
+        assert!(AntinodeRule::parse("-2").is_err());
+        assert!(AntinodeRule::parse("1/0").is_err());
+        assert!(AntinodeRule::parse("half").is_err());
+    }
+
+    #[test]
+    fn test_offset_by_zero_denominator() {
+        let pos = Pos([1, 1]);
+        assert_eq!(pos.offset(&[2, 2], 1, 0), None);
+        assert_eq!(pos.offset(&[2, 2], 1, 2), Some(Pos([2, 2])));
+        assert_eq!(Pos([0]).offset(&[isize::MIN], 1, -1), None);
+    }
+
+    #[test]
+    fn test_missing_map_is_an_error() {
+        let err = run("inputs/no-such-day08.txt", &[]).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::NotFound);
+    }
+
+    #[test]
+    fn test_crlf_layers() {
+        let lf = parse_layers("a..\n...\n\n...\n..a\n");
+        let crlf = parse_layers("a..\r\n...\r\n\r\n...\r\n..a\r\n");
+        assert_eq!(lf.len(), 2);
+        assert_eq!(crlf, lf);
+        // Extra blank lines or whitespace-only separators don't add layers
+        assert_eq!(parse_layers("\na..\n...\n \n\n...\n..a\n\n"), lf);
+    }
+
+    #[test]
+    fn test_layered_field() {
+        let layers = parse_layers("a..\n...\n...\n\n...\n...\n...\n\n...\n...\n..a\n");
+        assert_eq!(layers.len(), 3);
+        // Both antinodes twice as far fall outside the cube
+        let twice_as_far = Resonance::Ratio(AntinodeRule::times(2));
+        assert_eq!(calc_layers(&layers, &twice_as_far), 0);
+        assert_eq!(calc_layers(&layers, &Resonance::Pairs), 1);
+        assert_eq!(
+            calc_layers(&layers, &Resonance::Ratio(AntinodeRule::midpoint())),
+            1
+        );
+        assert_eq!(
+            calc_layers(&layers, &Resonance::Harmonic(Harmonics::Stepped)),
+            2
+        );
+        assert_eq!(
+            calc_layers(&layers, &Resonance::Harmonic(Harmonics::Exact)),
+            3
+        );
+    }
+
+    #[test]
+    fn test_exact_needs_harmonic_rule() {
+        let args = |args: &[&str]| args.iter().map(|a| a.to_string()).collect::<Vec<_>>();
+        for options in [
+            args(&["--exact", "--report"]),
+            args(&["--exact", "--overlay"]),
+            args(&["--exact", "--layered"]),
+            args(&["--exact", "--harmonic", "--ratio", "mid"]),
+            args(&["--harmonic", "--ratio", "mid"]),
+            args(&["--ratio", "2", "--harmonic", "--report"]),
+        ] {
+            let err = run("inputs/day08.txt", &options).unwrap_err();
+            assert_eq!(err.kind(), io::ErrorKind::InvalidInput, "{:?}", options);
+        }
+    }
+}
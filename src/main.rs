@@ -8,6 +8,9 @@ mod day07;
 mod day08;
 mod day09;
 
+use anyhow::anyhow;
+use std::env;
+
 macro_rules! solve_and_print {
     ($($day:expr, $file:expr, $solve_fn:expr);*) => {
         $(
@@ -20,15 +23,33 @@ macro_rules! solve_and_print {
 }
 
 fn main() {
-    solve_and_print!(
-        "Day 01", "inputs/day01.txt", day01::solve;
-        "Day 02", "inputs/day02.txt", day02::solve;
-        "Day 03", "inputs/day03.txt", day03::solve;
-        "Day 04", "inputs/day04.txt", day04::solve;
-        "Day 05", "inputs/day05.txt", day05::solve;
-        "Day 06", "inputs/day06.txt", day06::solve;
-        "Day 07", "inputs/day07.txt", day07::solve;
-        "Day 08", "inputs/day08.txt", day08::solve;
-        "Day 09", "inputs/day09.txt", day09::solve
-    );
+    let args: Vec<String> = env::args().skip(1).collect();
+    let Some((command, options)) = args.split_first() else {
+        solve_and_print!(
+            "Day 01", "inputs/day01.txt", day01::solve;
+            "Day 02", "inputs/day02.txt", day02::solve;
+            "Day 03", "inputs/day03.txt", day03::solve;
+            "Day 04", "inputs/day04.txt", day04::solve;
+            "Day 05", "inputs/day05.txt", day05::solve;
+            "Day 06", "inputs/day06.txt", day06::solve;
+            "Day 07", "inputs/day07.txt", day07::solve;
+            "Day 08", "inputs/day08.txt", day08::solve;
+            "Day 09", "inputs/day09.txt", day09::solve
+        );
+        return;
+    };
+
+    let result: anyhow::Result<()> = match command.as_str() {
+        "day01" => day01::run("inputs/day01.txt", options),
+        "day02" => day02::run("inputs/day02.txt", options),
+        "day03" => day03::run("inputs/day03.txt", options),
+        "day04" => day04::run("inputs/day04.txt", options).map_err(Into::into),
+        "day06" => day06::run("inputs/day06.txt", options).map_err(Into::into),
+        "day07" => day07::run("inputs/day07.txt", options).map_err(Into::into),
+        "day08" => day08::run("inputs/day08.txt", options).map_err(Into::into),
+        _ => Err(anyhow!("Unknown command: {}", command)),
+    };
+    if let Err(e) = result {
+        eprintln!("Failed to run {}: {:?}", command, e);
+    }
 }